@@ -0,0 +1,4825 @@
+#[macro_use]
+extern crate serde_derive;
+
+use std::io::{Read, Write};
+use std::fs::File;
+use std::error::Error;
+
+use std::cmp;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use rand::{FromEntropy, Rng, RngCore, SeedableRng};
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::distributions::{WeightedIndex, Distribution};
+
+use serde_json::{json, Value};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use tcod::colors::{self, Color};
+use tcod::console::*;
+use tcod::map::{FovAlgorithm, Map as FovMap};
+
+pub const SCREEN_WIDTH: i32 = 80;
+pub const SCREEN_HEIGHT: i32 = 50;
+
+pub const MAP_WIDTH: i32 = 80;
+pub const MAP_HEIGHT: i32 = 43;
+
+// the tile colors are only the fallback, the level's theme paints over them
+pub const DEFAULT_PALETTE: Palette = Palette {
+    dark_wall: Color { r: 0, g: 0, b: 100 },
+    light_wall: Color {r: 130, g: 110, b: 50},
+    dark_ground: Color {r: 50, g: 50, b: 150},
+    light_ground: Color {r: 200, g: 180, b: 50},
+    poulet: colors::GREY,
+    orc: colors::LIGHT_GREEN,
+    troll: colors::LIGHT_GREEN,
+    boss: colors::RED,
+    elemental: colors::FLAME,
+    remains: colors::DARK_RED,
+    combat: colors::WHITE,
+    item: colors::GREEN,
+    level: colors::VIOLET,
+    system: colors::LIGHT_GREY,
+    danger: colors::RED,
+};
+
+// walls and floor differ in brightness rather than hue, monsters use a blue/orange/yellow set
+pub const HIGH_CONTRAST_PALETTE: Palette = Palette {
+    dark_wall: Color {r: 90, g: 90, b: 90},
+    light_wall: Color {r: 235, g: 235, b: 235},
+    dark_ground: Color {r: 10, g: 10, b: 40},
+    light_ground: Color {r: 0, g: 90, b: 160},
+    poulet: Color {r: 240, g: 228, b: 66},
+    orc: Color {r: 230, g: 159, b: 0},
+    troll: Color {r: 86, g: 180, b: 233},
+    boss: Color {r: 213, g: 94, b: 0},
+    elemental: Color {r: 204, g: 121, b: 167},
+    remains: Color {r: 120, g: 120, b: 120},
+    combat: colors::WHITE,
+    item: Color {r: 0, g: 158, b: 115},
+    level: Color {r: 86, g: 180, b: 233},
+    system: Color {r: 180, g: 180, b: 180},
+    danger: Color {r: 213, g: 94, b: 0},
+};
+
+pub const ROOM_MAX_SIZE: i32 = 10;
+pub const ROOM_MIN_SIZE: i32 = 6;
+pub const MAX_ROOMS: i32 = 30;
+pub const LIT_ROOM_CHANCE: f32 = 0.3;
+pub const VAULT_CHANCE: f32 = 0.25;
+pub const VAULT_MIN_LEVEL: u32 = 3;
+pub const BOSS_LEVEL_INTERVAL: u32 = 5;
+pub const TRAP_MIN_LEVEL: u32 = 2;
+pub const MAX_TRAPS: i32 = 6;
+pub const SEARCH_RADIUS: f32 = 3.0;
+// how far from the player a drop-all spreads the pack, one item to a tile
+pub const DROP_SCATTER_RADIUS: i32 = 2;
+// the bottom of the dungeon, no stairs lead further down
+pub const FINAL_LEVEL: u32 = 10;
+
+pub const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
+pub const FOV_LIGHT_WALLS: bool = true;
+pub const TORCH_RADIUS: i32 = 5;
+
+pub const PLAYER: usize = 0;
+
+pub const BAR_WIDTH: i32 = 20;
+pub const PANEL_HEIGHT: i32 = 7;
+
+pub const HEAL_AMOUNT:i32 = 40;
+pub const ATTACK_BUFF:i32 = 2;
+pub const POTION_BUFF: i32 = 3;
+pub const POTION_BUFF_TURNS: i32 = 20;
+// haste doubles the speed and slow halves it
+pub const HASTE_SPEED: i32 = NORMAL_SPEED;
+pub const SLOW_SPEED: i32 = NORMAL_SPEED / 2;
+pub const HASTE_TURNS: i32 = 15;
+// a freezing blow leaves the player sluggish for a little while
+pub const CHILL_TURNS: i32 = 4;
+pub const LEVITATION_TURNS: i32 = 20;
+// what landing on the level below costs
+pub const FALL_DAMAGE: i32 = 12;
+// on difficulties with encumbrance, a pack over capacity slows the player down
+// and nothing more goes in past MAX_LOAD_PERCENT of it
+pub const CARRY_CAPACITY_BASE: i32 = 40;
+pub const CARRY_CAPACITY_PER_POWER: i32 = 4;
+pub const MAX_LOAD_PERCENT: i32 = 150;
+pub const BURDENED_SPEED_PENALTY: i32 = NORMAL_SPEED / 2;
+pub const PLAYER_MAX_ATTACK:i32 = 9;
+
+pub const MAGE_MANA: i32 = 20;
+pub const MANA_REGEN_INTERVAL: u64 = 3;
+
+// separate stacks the pack holds, worn equipment included
+pub const INVENTORY_LIMIT: usize = 26;
+pub const MAX_RINGS: usize = 2;
+pub const WAND_CHARGES: i32 = 5;
+pub const CURSED_CHANCE: f32 = 0.2;
+
+pub const MAX_NUTRITION: i32 = 1500;
+pub const HUNGRY_NUTRITION: i32 = 300;
+pub const RATION_NUTRITION: i32 = 800;
+pub const STARVATION_DAMAGE: i32 = 1;
+
+pub const LEVEL_UP_BASE: i32 = 200;
+pub const EXPLORER_XP_PENALTY: i32 = 50;
+pub const LEVEL_UP_FACTOR: i32 = 150;
+
+pub const MONSTER_LEVEL_UP_BASE: u32 = 20;
+pub const MONSTER_ATTACK_LEVEL_UP_BASE: u32 = 5;
+pub const MONSTER_DEFENSE_LEVEL_UP_BASE: u32 = 5;
+pub const MONSTER_LEVEL_UP_FACTOR: i32 = 2;
+
+pub const BOSS_SUMMON_INTERVAL: i32 = 6;
+// guardians and the lich fill a 2x2 square
+pub const BOSS_SIZE: i32 = 2;
+// everything banks its speed in energy each turn and spends ACTION_COST of it per action,
+// so a speed of 20 acts twice a turn and 5 every other turn
+pub const NORMAL_SPEED: i32 = 10;
+pub const ACTION_COST: i32 = 10;
+pub const WANDERING_MONSTER_INTERVAL: u64 = 150;
+pub const MONSTER_CHASE_TURNS: i32 = 5;
+pub const WAKE_RADIUS: f32 = 3.0;
+// how close an awake but unaware monster lets a moving player get before it notices,
+// standing still or sneaking only makes STILL_NOISE of that much noise
+pub const PERCEPTION_RADIUS: f32 = 4.0;
+pub const STILL_NOISE: f32 = 0.5;
+pub const SNEAK_SPEED_PENALTY: i32 = 2;
+// what a trap or chasm counts as, in steps of open floor, when a monster plans its way about
+pub const HAZARD_COST: u32 = 20;
+pub const TROLL_REGENERATION: i32 = 2;
+pub const ORC_RAGE_POWER: i32 = 3;
+pub const ORC_RAGE_TURNS: i32 = 10;
+pub const SNEAK_ATTACK_MULTIPLIER: i32 = 2;
+pub const ALLY_LIFETIME: i32 = 40;
+pub const ALLY_SIGHT_RANGE: f32 = 8.0;
+
+pub const POTION_APPEARANCES: &[&str] = &[
+    "cloudy potion", "bubbling potion", "murky potion", "fizzy potion", "smoky potion",
+    "golden potion", "oily potion", "milky potion", "glowing potion", "black potion",
+];
+pub const SCROLL_APPEARANCES: &[&str] = &[
+    "scroll labeled ZELGO MER", "scroll labeled FOOBIE BLETCH", "scroll labeled XIXAXA",
+    "scroll labeled ELBIB YLOH", "scroll labeled VERR YED", "scroll labeled PRATYAVAYAH",
+    "scroll labeled DAIYEN FOOELS", "scroll labeled KIRJE", "scroll labeled VE FORBRYDERNE",
+    "scroll labeled HACKEM MUCHE",
+];
+
+pub const SCORES_FILE: &str = "scores.json";
+pub const REMAINS_PREFIX: &str = "remains of ";
+pub const CONFIG_FILE: &str = "config.json";
+pub const SPAWNS_FILE: &str = "spawns.json";
+pub const SETTINGS_FILE: &str = "settings.json";
+pub const REPLAY_FILE: &str = "replay.json";
+pub const MAP_DUMP_FILE: &str = "map.txt";
+
+// bump with every change to the save layout and teach migrate_save the step up
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_MAGIC: &[u8] = b"RLSAVE";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Object {
+    pub x: i32,
+    pub y: i32,
+    pub char: char,
+    pub color: Color,
+    pub name: String,
+    pub blocks: bool,
+    pub alive: bool,
+    pub fighter: Option<Fighter>,
+    pub ai: Option<Ai>,
+    pub item:Option<Item>,
+    pub level: i32,
+    pub equipment: Option<Equipment>,
+    pub always_visible: bool,
+    #[serde(default = "default_identified")]
+    pub identified: bool,
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+    #[serde(default)]
+    pub last_seen: Option<(i32, i32)>,
+    #[serde(default)]
+    pub charges: Option<i32>,
+    #[serde(default)]
+    pub effects: Vec<StatusEffect>,
+    // a boss level's stairs stay sealed while its guardian lives
+    #[serde(default)]
+    pub guardian: bool,
+    // where it stood before the monsters last moved, so the step can be animated
+    #[serde(skip)]
+    pub previous_pos: Option<(i32, i32)>,
+    // big creatures cover size x size tiles, with (x, y) the top-left one
+    #[serde(default = "default_size")]
+    pub size: i32,
+    // per item in the stack
+    #[serde(default = "default_weight")]
+    weight: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EffectKind {
+    Strength,
+    Stoneskin,
+    Haste,
+    Slow,
+    Levitation,
+}
+
+impl EffectKind {
+    // short enough for a couple of them to share the side panel
+    pub fn abbreviation(self) -> &'static str {
+        match self {
+            EffectKind::Strength => "Str",
+            EffectKind::Stoneskin => "Skin",
+            EffectKind::Haste => "Fast",
+            EffectKind::Slow => "Slow",
+            EffectKind::Levitation => "Float",
+        }
+    }
+}
+
+impl std::fmt::Display for EffectKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            EffectKind::Strength => write!(f, "Strength"),
+            EffectKind::Stoneskin => write!(f, "Stoneskin"),
+            EffectKind::Haste => write!(f, "Haste"),
+            EffectKind::Slow => write!(f, "Slow"),
+            EffectKind::Levitation => write!(f, "Levitation"),
+        }
+    }
+}
+
+// a temporary change that wears off after a number of turns
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    pub amount: i32,
+    pub turns: i32,
+}
+
+fn default_identified() -> bool {
+    true
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+fn default_size() -> i32 {
+    1
+}
+
+fn default_weight() -> i32 {
+    1
+}
+
+// how many tiles lie between two spans along one axis, zero when they touch or overlap
+fn span_gap(start: i32, size: i32, other_start: i32, other_size: i32) -> i32 {
+    cmp::max(0, cmp::max(other_start - (start + size - 1), start - (other_start + other_size - 1)))
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub screen_width: i32,
+    pub screen_height: i32,
+    pub map_width: i32,
+    pub map_height: i32,
+    pub remember_monsters: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            screen_width: SCREEN_WIDTH,
+            screen_height: SCREEN_HEIGHT,
+            map_width: MAP_WIDTH,
+            map_height: MAP_HEIGHT,
+            remember_monsters: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn panel_y(&self) -> i32 {
+        self.screen_height - PANEL_HEIGHT
+    }
+
+    pub fn msg_width(&self) -> i32 {
+        self.screen_width - BAR_WIDTH - 2
+    }
+
+    // the map scrolls, so it only has to hold a couple of the biggest rooms
+    pub fn is_valid(&self) -> bool {
+        self.map_width >= 2 * BSP_MIN_LEAF
+            && self.map_height >= 2 * BSP_MIN_LEAF
+            && self.panel_y() > 0
+    }
+
+    // the part of the screen above the panel that shows the map
+    pub fn view_size(&self) -> (i32, i32) {
+        (self.screen_width, self.panel_y())
+    }
+}
+
+pub fn load_config() -> Config {
+    let config = match File::open(CONFIG_FILE) {
+        Ok(file) => serde_json::from_reader::<_, Config>(file).unwrap_or_else(|err| {
+            eprintln!("Ignoring {}: {}", CONFIG_FILE, err);
+            Config::default()
+        }),
+        Err(_) => Config::default(),
+    };
+
+    if config.is_valid() {
+        config
+    } else {
+        eprintln!("Ignoring {}: the map must fit on screen above the panel.", CONFIG_FILE);
+        Config::default()
+    }
+}
+
+pub const DEFAULT_FONT: &str = "arial10x10.png";
+pub const DEFAULT_FPS: i32 = 20;
+// below this animations would run for ever
+pub const MIN_ANIMATION_SPEED: f32 = 0.1;
+
+// how the glyphs are arranged in a font image, libtcod can't tell by itself
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum FontArrangement {
+    #[default]
+    Tcod,
+    AsciiInRow,
+    AsciiInCol,
+}
+
+impl FontArrangement {
+    pub fn next(self) -> Self {
+        match self {
+            FontArrangement::Tcod => FontArrangement::AsciiInRow,
+            FontArrangement::AsciiInRow => FontArrangement::AsciiInCol,
+            FontArrangement::AsciiInCol => FontArrangement::Tcod,
+        }
+    }
+}
+
+impl std::fmt::Display for FontArrangement {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            FontArrangement::Tcod => write!(f, "tcod"),
+            FontArrangement::AsciiInRow => write!(f, "ascii in rows"),
+            FontArrangement::AsciiInCol => write!(f, "ascii in columns"),
+        }
+    }
+}
+
+// a damage number shown where something got hurt, age counts frames drawn so far
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DamageLabel {
+    pub x: i32,
+    pub y: i32,
+    pub amount: i32,
+    pub age: u32,
+}
+
+// display choices the player makes in game that carry over to the next launch
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub fullscreen: bool,
+    // a png in the working directory, only read when the window opens
+    pub font: String,
+    pub font_arrangement: FontArrangement,
+    // frames drawn per second at most, 0 for no limit
+    pub fps: i32,
+    // 2.0 plays projectiles, monster moves and damage numbers in half the frames
+    pub animation_speed: f32,
+    // walking over ammo picks it up, anything else still needs 'f'
+    pub auto_pickup: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            fullscreen: false,
+            font: DEFAULT_FONT.into(),
+            font_arrangement: FontArrangement::default(),
+            fps: DEFAULT_FPS,
+            animation_speed: 1.0,
+            auto_pickup: false,
+        }
+    }
+}
+
+impl Settings {
+    // how many frames an animation written for the given count gets at the chosen speed
+    pub fn animation_frames(&self, frames: usize) -> usize {
+        let speed = self.animation_speed.max(MIN_ANIMATION_SPEED);
+        cmp::max(1, (frames as f32 / speed).round() as usize)
+    }
+}
+
+pub fn load_settings() -> Settings {
+    File::open(SETTINGS_FILE)
+        .ok()
+        .and_then(|file| serde_json::from_reader::<_, Settings>(file).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &Settings) -> Result<(), Box<dyn Error>> {
+    let file = File::create(SETTINGS_FILE)?;
+    serde_json::to_writer_pretty(file, settings)?;
+    Ok(())
+}
+
+// everything needed to play a run back exactly: where the dice started, the map size
+// and every key and click, in order; only good for the build that recorded it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: Config,
+    pub inputs: Vec<RecordedInput>,
+}
+
+impl Replay {
+    pub fn new(seed: u64, config: Config) -> Self {
+        Replay { seed, config, inputs: vec![] }
+    }
+
+    // runs of frames without input are counted rather than stored one by one
+    pub fn record(&mut self, input: RecordedInput) {
+        match (self.inputs.last_mut(), input) {
+            (Some(RecordedInput::Idle(frames)), RecordedInput::Idle(more)) => *frames += more,
+            _ => self.inputs.push(input),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordedInput {
+    // the code is tcod's own number for the key
+    Key { code: u32, printable: char, alt: bool, ctrl: bool, shift: bool },
+    Mouse { cx: i32, cy: i32, lbutton_pressed: bool, rbutton_pressed: bool },
+    // frames that went by with nothing pressed, a click-to-move walk keeps going during them
+    Idle(u32),
+}
+
+pub fn save_replay(replay: &Replay, path: &str) -> Result<(), Box<dyn Error>> {
+    let file = File::create(path)?;
+    serde_json::to_writer(file, replay)?;
+    Ok(())
+}
+
+pub fn load_replay(path: &str) -> Result<Replay, Box<dyn Error>> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Game {
+    pub map: Map,
+    pub log: Messages,
+    pub inventory: Vec<Object>,
+    pub dungeon_level: u32,
+    #[serde(default)]
+    pub palette: PaletteKind,
+    // show the turn each message happened on in the side panel too
+    #[serde(default)]
+    pub log_turns: bool,
+    #[serde(default)]
+    pub animate_moves: bool,
+    // float the damage dealt over whatever took it
+    #[serde(default)]
+    pub damage_numbers: bool,
+    // point the way to the stairs in the panel once they've been seen
+    #[serde(default)]
+    pub stairs_compass: bool,
+    // numbers still rising off their targets, the front-end ages them and lets them go
+    #[serde(skip)]
+    pub damage_labels: Vec<DamageLabel>,
+    #[serde(default)]
+    pub appearances: Vec<(Item, String)>,
+    #[serde(default)]
+    pub identified: Vec<Item>,
+    // objects created mid-turn (summons, loot) that join the level at the end of the turn
+    #[serde(default)]
+    pub pending_objects: Vec<Object>,
+    // monsters that dropped through a chasm, to land somewhere on the next level down
+    #[serde(default)]
+    pub fallen: Vec<Object>,
+    #[serde(default)]
+    pub turns: u64,
+    #[serde(default)]
+    pub last_attacker: Option<String>,
+    #[serde(default = "default_nutrition")]
+    pub nutrition: i32,
+    #[serde(default)]
+    pub generator: GeneratorKind,
+    #[serde(default)]
+    pub theme: ThemeKind,
+    #[serde(default)]
+    pub lit_rooms: Vec<Rect>,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    #[serde(default)]
+    pub mode: GameMode,
+    // where the player arrived on this level, explorer mode respawns them there
+    #[serde(default)]
+    pub entrance: Option<(i32, i32)>,
+    #[serde(default)]
+    pub won: bool,
+    #[serde(default)]
+    pub class: PlayerClass,
+    // the number keys cast these in order
+    #[serde(default)]
+    pub spells: Vec<Spell>,
+    // moving quietly, slower but monsters have to be closer to notice
+    #[serde(default)]
+    pub sneaking: bool,
+    #[serde(skip)]
+    pub player_moved: bool,
+}
+
+fn default_nutrition() -> i32 {
+    MAX_NUTRITION
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub dungeon_level: u32,
+    pub player_level: i32,
+    pub xp: i32,
+    pub turns: u64,
+    pub cause: String,
+}
+
+impl ScoreEntry {
+    pub fn new(player: &Object, game: &Game, cause: String) -> Self {
+        ScoreEntry {
+            dungeon_level: game.dungeon_level,
+            player_level: player.level,
+            xp: player.fighter.map_or(0, |f| f.xp),
+            turns: game.turns,
+            cause,
+        }
+    }
+}
+
+impl Game {
+    pub fn is_identified(&self, item: Item) -> bool {
+        !item.is_magic() || self.identified.contains(&item)
+    }
+
+    pub fn display_name(&self, object: &Object) -> String {
+        match object.item {
+            Some(item) if !object.identified && !self.is_identified(item) => self
+                .appearances
+                .iter()
+                .find(|&&(kind, _)| kind == item)
+                .map_or(object.name.clone(), |(_, appearance)| appearance.clone()),
+            _ => object.name.clone(),
+        }
+    }
+
+    pub fn identify(&mut self, item: Item) {
+        if !self.identified.contains(&item) {
+            self.identified.push(item);
+        }
+        for object in self.inventory.iter_mut().filter(|o| o.item == Some(item)) {
+            object.identified = true;
+        }
+    }
+}
+
+thread_local! {
+    static GAME_RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+// every random roll goes through here so a whole game can be replayed from a seed
+pub struct GameRng;
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        GAME_RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        GAME_RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        GAME_RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        GAME_RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+pub fn rng() -> GameRng {
+    GameRng
+}
+
+pub fn seed_rng(seed: u64) {
+    GAME_RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+// a fresh seed to start a game from, kept so the run can be played back
+pub fn new_seed() -> u64 {
+    SmallRng::from_entropy().next_u64()
+}
+
+pub fn random_appearances() -> Vec<(Item, String)> {
+    let mut rng = rng();
+    let mut potions = POTION_APPEARANCES.to_vec();
+    let mut scrolls = SCROLL_APPEARANCES.to_vec();
+    potions.shuffle(&mut rng);
+    scrolls.shuffle(&mut rng);
+
+    let potion_kinds = Item::ALL.iter().filter(|i| i.is_potion());
+    let scroll_kinds = Item::ALL.iter().filter(|i| i.is_scroll());
+
+    potion_kinds
+        .zip(potions)
+        .chain(scroll_kinds.zip(scrolls))
+        .map(|(&item, appearance)| (item, appearance.to_string()))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub dark_wall: Color,
+    pub light_wall: Color,
+    pub dark_ground: Color,
+    pub light_ground: Color,
+    pub poulet: Color,
+    pub orc: Color,
+    pub troll: Color,
+    pub boss: Color,
+    pub elemental: Color,
+    pub remains: Color,
+    pub combat: Color,
+    pub item: Color,
+    pub level: Color,
+    pub system: Color,
+    pub danger: Color,
+}
+
+impl Palette {
+    pub fn color_of(&self, object: &Object) -> Color {
+        if object.is_remains() {
+            // tint the remains with the species color so bodies stay tellable apart
+            let species = &object.name[REMAINS_PREFIX.len()..];
+            return match self.species_color(species) {
+                Some(color) => colors::lerp(color, self.remains, 0.5),
+                None => object.color,
+            };
+        }
+        self.species_color(&object.name).unwrap_or(object.color)
+    }
+
+    pub fn message_color(&self, category: MessageCategory) -> Color {
+        match category {
+            MessageCategory::Combat => self.combat,
+            MessageCategory::Item => self.item,
+            MessageCategory::Level => self.level,
+            MessageCategory::System => self.system,
+            MessageCategory::Danger => self.danger,
+        }
+    }
+
+    pub fn species_color(&self, name: &str) -> Option<Color> {
+        match name {
+            "poulet" => Some(self.poulet),
+            "orc" => Some(self.orc),
+            "troll" => Some(self.troll),
+            "BOSS" => Some(self.boss),
+            "fire elemental" => Some(self.elemental),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    #[default]
+    Default,
+    HighContrast,
+}
+
+impl PaletteKind {
+    pub fn palette(self) -> &'static Palette {
+        match self {
+            PaletteKind::Default => &DEFAULT_PALETTE,
+            PaletteKind::HighContrast => &HIGH_CONTRAST_PALETTE,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            PaletteKind::Default => PaletteKind::HighContrast,
+            PaletteKind::HighContrast => PaletteKind::Default,
+        }
+    }
+
+    // the high contrast palette keeps its own tiles, legibility wins over atmosphere
+    pub fn themed(self, theme: ThemeKind) -> Palette {
+        let mut palette = *self.palette();
+        if self == PaletteKind::Default {
+            let theme = theme.theme();
+            palette.dark_wall = theme.dark_wall;
+            palette.light_wall = theme.light_wall;
+            palette.dark_ground = theme.dark_ground;
+            palette.light_ground = theme.light_ground;
+        }
+        palette
+    }
+}
+
+// weights are percentages of the depth-scaled chances, anything unlisted stays at 100
+#[derive(Clone, Copy, Debug)]
+pub struct DungeonTheme {
+    pub name: &'static str,
+    pub dark_wall: Color,
+    pub light_wall: Color,
+    pub dark_ground: Color,
+    pub light_ground: Color,
+    pub monster_weights: &'static [(&'static str, u32)],
+    pub item_weights: &'static [(Item, u32)],
+}
+
+impl DungeonTheme {
+    pub fn monster_weight(&self, name: &str, chance: u32) -> u32 {
+        let percent = self.monster_weights.iter().find(|&&(other, _)| other == name).map_or(100, |&(_, percent)| percent);
+        chance * percent / 100
+    }
+
+    pub fn item_weight(&self, item: Item, chance: u32) -> u32 {
+        let percent = self.item_weights.iter().find(|&&(other, _)| other == item).map_or(100, |&(_, percent)| percent);
+        chance * percent / 100
+    }
+}
+
+pub const CAVES_THEME: DungeonTheme = DungeonTheme {
+    name: "caves",
+    dark_wall: Color { r: 0, g: 0, b: 100 },
+    light_wall: Color {r: 130, g: 110, b: 50},
+    dark_ground: Color {r: 50, g: 50, b: 150},
+    light_ground: Color {r: 200, g: 180, b: 50},
+    monster_weights: &[("poulet", 150), ("troll", 50)],
+    item_weights: &[(Item::Ration, 150), (Item::Arrow, 150)],
+};
+
+pub const CRYPT_THEME: DungeonTheme = DungeonTheme {
+    name: "crypt",
+    dark_wall: Color {r: 30, g: 20, b: 50},
+    light_wall: Color {r: 110, g: 100, b: 130},
+    dark_ground: Color {r: 45, g: 40, b: 60},
+    light_ground: Color {r: 150, g: 145, b: 160},
+    monster_weights: &[("poulet", 50), ("orc", 150), ("boss", 150)],
+    item_weights: &[(Item::IdentifyScroll, 200), (Item::RemoveCurse, 200), (Item::Heal, 75)],
+};
+
+pub const FORGE_THEME: DungeonTheme = DungeonTheme {
+    name: "forge",
+    dark_wall: Color {r: 60, g: 15, b: 10},
+    light_wall: Color {r: 150, g: 60, b: 30},
+    dark_ground: Color {r: 70, g: 40, b: 30},
+    light_ground: Color {r: 200, g: 120, b: 60},
+    monster_weights: &[("fire elemental", 300), ("troll", 75)],
+    item_weights: &[(Item::Sword, 200), (Item::Greatsword, 200), (Item::Targe, 200), (Item::Chest, 150)],
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ThemeKind {
+    #[default]
+    Caves,
+    Crypt,
+    Forge,
+}
+
+impl ThemeKind {
+    // three levels per band, the forge runs all the way down
+    pub fn for_level(level: u32) -> Self {
+        match level {
+            0..=3 => ThemeKind::Caves,
+            4..=6 => ThemeKind::Crypt,
+            _ => ThemeKind::Forge,
+        }
+    }
+
+    pub fn theme(self) -> &'static DungeonTheme {
+        match self {
+            ThemeKind::Caves => &CAVES_THEME,
+            ThemeKind::Crypt => &CRYPT_THEME,
+            ThemeKind::Forge => &FORGE_THEME,
+        }
+    }
+}
+
+impl std::fmt::Display for PaletteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PaletteKind::Default => write!(f, "default"),
+            PaletteKind::HighContrast => write!(f, "high contrast"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
+}
+
+fn transitions(table: &[(u32, u32)]) -> Vec<Transition> {
+    table.iter().map(|&(level, value)| Transition { level, value }).collect()
+}
+
+fn default_on_death() -> DeathCallback {
+    DeathCallback::Monster
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonsterTemplate {
+    // what create_monster and the themes call it, the name is what the player sees
+    pub key: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub xp: i32,
+    // which stats grow with the dungeon level
+    #[serde(default)]
+    pub scale_hp: bool,
+    #[serde(default)]
+    pub scale_defense: bool,
+    #[serde(default)]
+    pub scale_power: bool,
+    #[serde(default)]
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub resistances: Resistances,
+    #[serde(default = "default_on_death")]
+    pub on_death: DeathCallback,
+    // bosses summon help instead of just chasing the player
+    #[serde(default)]
+    pub boss: bool,
+    #[serde(default = "default_size")]
+    pub size: i32,
+    #[serde(default = "default_speed")]
+    pub speed: i32,
+    #[serde(default = "default_perception")]
+    pub perception: f32,
+    // charges straight at the player, traps and chasms or not
+    #[serde(default)]
+    pub reckless: bool,
+    // chance of turning up by dungeon level, empty for monsters only placed on purpose
+    #[serde(default)]
+    pub weights: Vec<Transition>,
+}
+
+impl MonsterTemplate {
+    // the template's stats, grown to suit the dungeon level where it asks for that
+    pub fn fighter(&self, level: u32) -> Fighter {
+        let hp_multiplier = (MONSTER_LEVEL_UP_BASE as i32 + level as i32) / MONSTER_LEVEL_UP_FACTOR;
+        let attack_multiplier = (MONSTER_ATTACK_LEVEL_UP_BASE as i32 + level as i32) / MONSTER_LEVEL_UP_FACTOR;
+        let defense_multiplier = (MONSTER_DEFENSE_LEVEL_UP_BASE as i32 + level as i32) / MONSTER_LEVEL_UP_FACTOR;
+        let scaled = |base: i32, scales: bool, multiplier: i32| if scales { base + multiplier } else { base };
+        let hp = scaled(self.hp, self.scale_hp, hp_multiplier);
+        Fighter {
+            base_max_hp: hp,
+            hp,
+            base_defense: scaled(self.defense, self.scale_defense, defense_multiplier),
+            base_power: scaled(self.power, self.scale_power, attack_multiplier),
+            on_death: self.on_death,
+            damage_type: self.damage_type,
+            resistances: self.resistances,
+            mana: 0,
+            max_mana: 0,
+            speed: self.speed,
+            energy: 0,
+            perception: self.perception,
+            reckless: self.reckless,
+            xp: self.xp,
+        }
+    }
+
+    pub fn ai(&self) -> Ai {
+        if self.boss {
+            Ai::Boss { seen: false, summon_cooldown: BOSS_SUMMON_INTERVAL }
+        } else {
+            Ai::Basic { last_known: None, turns: 0 }
+        }
+    }
+}
+
+fn default_quantity_range() -> (u32, u32) {
+    (1, 1)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemTemplate {
+    pub item: Item,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    // files written before items named their callback get the built-in one for the kind
+    #[serde(default)]
+    pub on_use: Option<UseCallback>,
+    #[serde(default)]
+    pub equipment: Option<Equipment>,
+    // how many come in a stack, both ends included
+    #[serde(default = "default_quantity_range")]
+    pub quantity: (u32, u32),
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    #[serde(default)]
+    pub weights: Vec<Transition>,
+    // the longer story told when the player examines one, the built-in one if left out
+    #[serde(default)]
+    pub lore: String,
+}
+
+// everything place_object can put in a room, loaded from SPAWNS_FILE when there is one
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpawnTable {
+    pub max_monsters: Vec<Transition>,
+    pub max_items: Vec<Transition>,
+    pub monsters: Vec<MonsterTemplate>,
+    pub items: Vec<ItemTemplate>,
+}
+
+impl Default for SpawnTable {
+    fn default() -> Self {
+        SpawnTable {
+            max_monsters: transitions(&[(1, 2), (4, 3), (6, 5), (10, 7)]),
+            max_items: transitions(&[(1, 1), (4, 2)]),
+            monsters: vec![
+                MonsterTemplate {
+                    key: "poulet".into(),
+                    name: "poulet".into(),
+                    glyph: 'p',
+                    color: colors::GREY,
+                    hp: 15,
+                    defense: 0,
+                    power: 3,
+                    xp: 20,
+                    scale_hp: false,
+                    scale_defense: false,
+                    scale_power: false,
+                    damage_type: DamageType::Physical,
+                    resistances: Resistances::default(),
+                    on_death: DeathCallback::Monster,
+                    boss: false,
+                    size: 1,
+                    speed: NORMAL_SPEED,
+                    perception: PERCEPTION_RADIUS,
+                    reckless: false,
+                    weights: transitions(&[(1, 60), (2, 30), (4, 0)]),
+                },
+                MonsterTemplate {
+                    key: "orc".into(),
+                    name: "orc".into(),
+                    glyph: 'o',
+                    color: colors::LIGHT_GREEN,
+                    hp: 10,
+                    defense: 0,
+                    power: 4,
+                    xp: 35,
+                    scale_hp: true,
+                    scale_defense: true,
+                    scale_power: true,
+                    damage_type: DamageType::Physical,
+                    resistances: Resistances::default(),
+                    on_death: DeathCallback::Monster,
+                    boss: false,
+                    size: 1,
+                    speed: NORMAL_SPEED,
+                    perception: PERCEPTION_RADIUS,
+                    reckless: false,
+                    weights: transitions(&[(2, 30), (5, 30)]),
+                },
+                MonsterTemplate {
+                    key: "troll".into(),
+                    name: "troll".into(),
+                    glyph: 'T',
+                    color: colors::LIGHT_GREEN,
+                    hp: 15,
+                    defense: 1,
+                    power: 5,
+                    xp: 55,
+                    scale_hp: true,
+                    scale_defense: true,
+                    scale_power: true,
+                    damage_type: DamageType::Physical,
+                    resistances: Resistances::default(),
+                    on_death: DeathCallback::Monster,
+                    boss: false,
+                    size: 1,
+                    speed: NORMAL_SPEED,
+                    perception: 3.0,
+                    reckless: true,
+                    weights: transitions(&[(4, 15), (5, 30), (7, 60)]),
+                },
+                MonsterTemplate {
+                    key: "boss".into(),
+                    name: "BOSS".into(),
+                    glyph: 'W',
+                    color: colors::RED,
+                    hp: 60,
+                    defense: 4,
+                    power: 8,
+                    xp: 110,
+                    scale_hp: true,
+                    scale_defense: true,
+                    scale_power: true,
+                    damage_type: DamageType::Physical,
+                    resistances: Resistances::default(),
+                    on_death: DeathCallback::Boss,
+                    boss: true,
+                    size: 1,
+                    speed: NORMAL_SPEED,
+                    perception: PERCEPTION_RADIUS,
+                    reckless: false,
+                    weights: transitions(&[(3, 10), (5, 15), (7, 20)]),
+                },
+                MonsterTemplate {
+                    key: "fire elemental".into(),
+                    name: "fire elemental".into(),
+                    glyph: 'E',
+                    color: colors::FLAME,
+                    hp: 12,
+                    defense: 1,
+                    power: 5,
+                    xp: 60,
+                    scale_hp: true,
+                    scale_defense: false,
+                    scale_power: true,
+                    damage_type: DamageType::Fire,
+                    resistances: Resistances { fire: 75, cold: -50, ..Resistances::default() },
+                    on_death: DeathCallback::Monster,
+                    boss: false,
+                    size: 1,
+                    speed: NORMAL_SPEED,
+                    perception: PERCEPTION_RADIUS,
+                    reckless: false,
+                    weights: transitions(&[(4, 10), (6, 20)]),
+                },
+                // only ever found on the final level
+                MonsterTemplate {
+                    key: "lich".into(),
+                    name: "lich".into(),
+                    glyph: 'L',
+                    color: colors::LIGHT_PURPLE,
+                    hp: 150,
+                    defense: 8,
+                    power: 14,
+                    xp: 500,
+                    scale_hp: false,
+                    scale_defense: false,
+                    scale_power: false,
+                    damage_type: DamageType::Cold,
+                    resistances: Resistances { cold: 75, lightning: 25, fire: -25, ..Resistances::default() },
+                    on_death: DeathCallback::FinalBoss,
+                    boss: true,
+                    size: BOSS_SIZE,
+                    speed: NORMAL_SPEED,
+                    perception: PERCEPTION_RADIUS,
+                    reckless: false,
+                    weights: vec![],
+                },
+            ],
+            items: vec![
+                item_template(Item::Heal, '!', "healing potion", colors::VIOLET, UseCallback::Heal, &[(1, 35)]),
+                item_template(Item::Lightning, '#', "scroll of lightning bolt", colors::LIGHT_YELLOW, UseCallback::Lightning, &[(4, 10)]),
+                ItemTemplate {
+                    equipment: Some(gear(Slot::RightHand, 3, 0, 0)),
+                    weight: 8,
+                    ..item_template(Item::Sword, '/', "sword", colors::SKY, UseCallback::Equip, &[(3, 5)])
+                },
+                ItemTemplate {
+                    equipment: Some(gear(Slot::LeftHand, 0, 1, 0)),
+                    weight: 6,
+                    ..item_template(Item::Targe, '◙', "targe", colors::DARK_HAN, UseCallback::Equip, &[(6, 5)])
+                },
+                ItemTemplate {
+                    equipment: Some(gear(Slot::Chest, 0, 2, 10)),
+                    weight: 15,
+                    ..item_template(Item::Chest, '░', "chainmail armor", colors::COPPER, UseCallback::Equip, &[(8, 5)])
+                },
+                item_template(Item::IdentifyScroll, '#', "scroll of identify", colors::LIGHT_CYAN, UseCallback::Identify, &[(2, 10)]),
+                item_template(Item::RemoveCurse, '#', "scroll of remove curse", colors::WHITE, UseCallback::RemoveCurse, &[(3, 5)]),
+                ItemTemplate {
+                    quantity: (3, 6),
+                    ..item_template(Item::Arrow, '^', "arrow", colors::LIGHT_SEPIA, UseCallback::Shoot, &[(1, 15)])
+                },
+                ItemTemplate {
+                    weight: 2,
+                    ..item_template(Item::Ration, '%', "ration", colors::DARKER_ORANGE, UseCallback::Eat, &[(1, 20)])
+                },
+                item_template(Item::MapScroll, '#', "scroll of mapping", colors::LIGHT_GREEN, UseCallback::Mapping, &[(4, 8)]),
+                item_template(Item::SummonAlly, '#', "scroll of summoning", colors::LIGHT_CYAN, UseCallback::SummonAlly, &[(3, 5)]),
+                ItemTemplate {
+                    equipment: Some(gear(Slot::BothHands, 6, 0, 0)),
+                    weight: 14,
+                    ..item_template(Item::Greatsword, '/', "greatsword", colors::LIGHT_SKY, UseCallback::Equip, &[(5, 4)])
+                },
+                ItemTemplate {
+                    equipment: Some(gear(Slot::Ring, 1, 0, 0)),
+                    ..item_template(Item::PowerRing, '=', "ring of power", colors::GOLD, UseCallback::Equip, &[(4, 3)])
+                },
+                ItemTemplate {
+                    equipment: Some(gear(Slot::Ring, 0, 0, 5)),
+                    ..item_template(Item::VigorRing, '=', "ring of vigor", colors::LIGHT_RED, UseCallback::Equip, &[(4, 3)])
+                },
+                ItemTemplate {
+                    weight: 2,
+                    ..item_template(Item::LightningWand, '-', "wand of lightning", colors::LIGHT_YELLOW, UseCallback::Lightning, &[(5, 3)])
+                },
+                item_template(Item::Recharge, '#', "scroll of recharging", colors::LIGHT_ORANGE, UseCallback::Recharge, &[(6, 3)]),
+                item_template(Item::StrengthPotion, '!', "potion of strength", colors::ORANGE, UseCallback::Strength, &[(2, 5)]),
+                item_template(Item::StoneskinPotion, '!', "potion of stoneskin", colors::LIGHT_GREY, UseCallback::Stoneskin, &[(2, 5)]),
+                item_template(Item::Clairvoyance, '#', "scroll of clairvoyance", colors::LIGHT_AZURE, UseCallback::Clairvoyance, &[(3, 4)]),
+                item_template(Item::HastePotion, '!', "potion of haste", colors::YELLOW, UseCallback::Haste, &[(4, 4)]),
+                item_template(Item::LevitationPotion, '!', "potion of levitation", colors::LIGHTEST_SKY, UseCallback::Levitate, &[(3, 3)]),
+                item_template(Item::AttackBuff, '+', "attack scroll", colors::VIOLET, UseCallback::AttackBuff, &[]),
+            ],
+        }
+    }
+}
+
+fn item_template(item: Item, glyph: char, name: &str, color: Color, on_use: UseCallback, weights: &[(u32, u32)]) -> ItemTemplate {
+    ItemTemplate {
+        item,
+        name: name.into(),
+        glyph,
+        color,
+        on_use: Some(on_use),
+        equipment: None,
+        quantity: default_quantity_range(),
+        weight: default_weight(),
+        weights: transitions(weights),
+        lore: item.default_lore().into(),
+    }
+}
+
+fn gear(slot: Slot, power_bonus: i32, defense_bonus: i32, max_hp_bonus: i32) -> Equipment {
+    Equipment { slot, equipped: false, power_bonus, defense_bonus, max_hp_bonus, cursed: false }
+}
+
+impl SpawnTable {
+    pub fn monster(&self, key: &str) -> Option<&MonsterTemplate> {
+        self.monsters.iter().find(|template| template.key == key)
+    }
+
+    pub fn item(&self, item: Item) -> Option<&ItemTemplate> {
+        self.items.iter().find(|template| template.item == item)
+    }
+
+    // a file only has to list what it changes, anything it leaves out keeps the built-in entry
+    pub fn merged_over(self, base: SpawnTable) -> SpawnTable {
+        let mut merged = base;
+        merged.max_monsters = self.max_monsters;
+        merged.max_items = self.max_items;
+        for template in self.monsters {
+            match merged.monsters.iter_mut().find(|other| other.key == template.key) {
+                Some(other) => *other = template,
+                None => merged.monsters.push(template),
+            }
+        }
+        for template in self.items {
+            match merged.items.iter_mut().find(|other| other.item == template.item) {
+                Some(other) => {
+                    let on_use = template.on_use.or(other.on_use);
+                    let lore = if template.lore.is_empty() { other.lore.clone() } else { template.lore.clone() };
+                    *other = ItemTemplate { on_use, lore, ..template };
+                }
+                None => merged.items.push(template),
+            }
+        }
+        merged
+    }
+
+    // every level has to be able to roll at least one monster and one item, once its theme has
+    // scaled the chances
+    pub fn is_valid(&self) -> bool {
+        (1..=FINAL_LEVEL).all(|level| {
+            let theme = ThemeKind::for_level(level).theme();
+            self.monsters
+                .iter()
+                .any(|template| theme.monster_weight(&template.key, from_dungeon_level(&template.weights, level)) > 0)
+                && self
+                    .items
+                    .iter()
+                    .any(|template| theme.item_weight(template.item, from_dungeon_level(&template.weights, level)) > 0)
+        })
+    }
+}
+
+thread_local! {
+    static SPAWN_TABLE: RefCell<SpawnTable> = RefCell::new(SpawnTable::default());
+}
+
+pub fn set_spawn_table(table: SpawnTable) {
+    SPAWN_TABLE.with(|spawns| *spawns.borrow_mut() = table);
+}
+
+pub fn with_spawn_table<T>(f: impl FnOnce(&SpawnTable) -> T) -> T {
+    SPAWN_TABLE.with(|spawns| f(&spawns.borrow()))
+}
+
+pub fn load_spawn_table() -> SpawnTable {
+    let file = match File::open(SPAWNS_FILE) {
+        Ok(file) => file,
+        Err(_) => return SpawnTable::default(),
+    };
+    match serde_json::from_reader::<_, SpawnTable>(file) {
+        Ok(table) => {
+            let table = table.merged_over(SpawnTable::default());
+            if table.is_valid() {
+                table
+            } else {
+                eprintln!("Ignoring {}: every level needs something to spawn.", SPAWNS_FILE);
+                SpawnTable::default()
+            }
+        }
+        Err(err) => {
+            eprintln!("Ignoring {}: {}", SPAWNS_FILE, err);
+            SpawnTable::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub slot: Slot,
+    pub equipped: bool,
+    pub max_hp_bonus: i32,
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    #[serde(default)]
+    pub cursed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Slot {
+    RightHand,
+    LeftHand,
+    BothHands,
+    Chest,
+    Ring,
+}
+
+impl Slot {
+    // a two-handed item takes up each hand, so it clashes with either one
+    pub fn overlaps(self, other: Slot) -> bool {
+        match (self, other) {
+            (Slot::BothHands, Slot::RightHand)
+            | (Slot::BothHands, Slot::LeftHand)
+            | (Slot::RightHand, Slot::BothHands)
+            | (Slot::LeftHand, Slot::BothHands) => true,
+            _ => self == other,
+        }
+    }
+
+    // how many items can be worn in the slot at once
+    pub fn capacity(self) -> usize {
+        match self {
+            Slot::Ring => MAX_RINGS,
+            _ => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Slot::LeftHand => write!(f, "left hand"),
+            Slot::RightHand => write!(f, "right hand"),
+            Slot::BothHands => write!(f, "both hands"),
+            Slot::Chest => write!(f, "chest"),
+            Slot::Ring => write!(f, "finger"),
+        }
+    }
+}
+
+// what the player can currently see; the game uses the tcod FovMap, tests can hand-craft one
+pub trait Visibility {
+    fn is_in_fov(&self, x: i32, y: i32) -> bool;
+}
+
+impl Visibility for FovMap {
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        FovMap::is_in_fov(self, x, y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MessageCategory {
+    Combat,
+    Item,
+    Level,
+    System,
+    Danger,
+}
+
+pub trait MessageLog {
+    fn add<T: Into<String>>(&mut self, message: T, color: Color);
+    // colored by the palette when drawn rather than fixed at the call site
+    fn add_categorized<T: Into<String>>(&mut self, message: T, category: MessageCategory);
+}
+
+impl MessageLog for Messages {
+    fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        self.push(message.into(), color, None);
+    }
+
+    fn add_categorized<T: Into<String>>(&mut self, message: T, category: MessageCategory) {
+        let color = DEFAULT_PALETTE.message_color(category);
+        self.push(message.into(), color, Some(category));
+    }
+}
+
+impl Messages {
+    fn push(&mut self, message: String, color: Color, category: Option<MessageCategory>) {
+        // a message repeating the last one bumps its "(xN)" count instead
+        if let Some(last) = self.entries.last_mut() {
+            if last.color == color && last.category == category {
+                let repeats = if last.text == message {
+                    Some(1)
+                } else {
+                    last.text.strip_prefix(message.as_str())
+                        .and_then(|rest| rest.strip_prefix(" (x"))
+                        .and_then(|rest| rest.strip_suffix(')'))
+                        .and_then(|count| count.parse::<u32>().ok())
+                };
+                if let Some(repeats) = repeats {
+                    last.text = format!("{} (x{})", message, repeats + 1);
+                    last.turn = self.turn;
+                    return;
+                }
+            }
+        }
+        self.entries.push(LogEntry { text: message, color, turn: self.turn, category });
+    }
+}
+
+impl Object {
+
+    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool,) -> Self {
+        Object{
+            x,
+            y,
+            char,
+            color,
+            name: name.into(),
+            blocks,
+            alive:false,
+            fighter: None,
+            ai: None,
+            item: None,
+            level: 1,
+            equipment: None,
+            always_visible: false,
+            identified: true,
+            quantity: 1,
+            last_seen: None,
+            charges: None,
+            effects: vec![],
+            guardian: false,
+            previous_pos: None,
+            size: 1,
+            weight: default_weight(),
+        }
+    }
+
+    // camera is the map position shown in the console's top-left corner
+    pub fn draw(&self, con: &mut dyn Console, palette: &Palette, camera: (i32, i32)){
+        con.set_default_foreground(palette.color_of(self));
+        for (x, y) in self.cells() {
+            let (x, y) = (x - camera.0, y - camera.1);
+            if x < 0 || y < 0 || x >= con.width() || y >= con.height() {
+                continue;
+            }
+            con.put_char(x, y, self.char, BackgroundFlag::None);
+        }
+    }
+
+    pub fn category(&self) -> ItemCategory {
+        match self.item {
+            _ if self.equipment.is_some() => ItemCategory::Equipment,
+            Some(item) if item.is_magic() || item == Item::Ration => ItemCategory::Consumable,
+            _ => ItemCategory::Misc,
+        }
+    }
+
+    pub fn total_weight(&self) -> i32 {
+        self.weight * self.quantity as i32
+    }
+
+    // consumables of the same kind share one inventory slot
+    pub fn stacks_with(&self, other: &Object) -> bool {
+        self.item.is_some()
+            && self.item == other.item
+            && self.equipment.is_none()
+            && other.equipment.is_none()
+            && self.charges.is_none()
+    }
+
+    pub fn pos(&self) -> (i32, i32){
+        (self.x, self.y)
+    }
+
+    pub fn set_pos(&mut self, x: i32, y: i32){
+        self.x = x;
+        self.y = y;
+    }
+
+    // measured between the nearest tiles of the two footprints
+    pub fn distance_to(&self, other: &Object) -> f32 {
+        let dx = span_gap(self.x, self.size, other.x, other.size);
+        let dy = span_gap(self.y, self.size, other.y, other.size);
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        let dx = span_gap(self.x, self.size, x, 1);
+        let dy = span_gap(self.y, self.size, y, 1);
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+
+    pub fn occupies(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.size && y >= self.y && y < self.y + self.size
+    }
+
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        (self.x..self.x + self.size)
+            .flat_map(|x| (self.y..self.y + self.size).map(move |y| (x, y)))
+            .collect()
+    }
+
+    // what a hit of the given kind actually costs this object once resistances are counted
+    pub fn resisted_damage(&self, damage: i32, damage_type: DamageType) -> i32 {
+        let resistance = self.fighter.map_or(0, |f| f.resistances.against(damage_type));
+        damage * (100 - resistance) / 100
+    }
+
+    // attacker is where the blow came from, if anyone dealt it
+    pub fn take_damage(&mut self, damage: i32, damage_type: DamageType, attacker: Option<(i32, i32)>, game: &mut Game) -> Option<i32> {
+        let damage = self.resisted_damage(damage, damage_type);
+
+        //borrowed
+        if let Some(fighter) = self.fighter.as_mut() {
+            if damage > 0 {
+                fighter.hp -= damage;
+            }
+        }
+        if damage > 0 && self.fighter.is_some() && game.damage_numbers {
+            game.damage_labels.push(DamageLabel { x: self.x, y: self.y, amount: damage, age: 0 });
+        }
+
+        //Copy
+        if let Some(fighter) = self.fighter {
+            if fighter.hp <= 0 {
+                self.alive = false;
+                fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
+            }
+        }
+
+        if self.ai == Some(Ai::Sleeping) {
+            self.wake_up(game);
+        }
+        // being hit gives the attacker away, however far off or quiet they are
+        if let (Some(pos), Some(Ai::Basic { last_known: None, .. })) = (attacker, self.ai) {
+            self.ai = Some(Ai::Basic { last_known: Some(pos), turns: MONSTER_CHASE_TURNS });
+        }
+        None
+    }
+
+    pub fn wake_up(&mut self, game: &mut Game) {
+        self.ai = Some(Ai::Basic { last_known: None, turns: 0 });
+        game.log.add_categorized(format!("The {} wakes up!", self.name), MessageCategory::Danger);
+    }
+
+    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
+
+        let sneak_attack = target.is_unaware();
+        let power = if sneak_attack {
+            self.power(game) * SNEAK_ATTACK_MULTIPLIER
+        } else {
+            self.power(game)
+        };
+        let mut damage = power - target.defense(game);
+
+        if sneak_attack {
+            game.log.add_categorized("Sneak attack!", MessageCategory::Combat);
+        } else if rng().gen::<f32>() <0.1 {
+            damage = -1;
+        }
+
+        let damage_type = self.fighter.map_or(DamageType::Physical, |f| f.damage_type);
+        if damage > 0 {
+            let dealt = target.resisted_damage(damage, damage_type);
+            game.log.add_categorized(format!("{} attacks {} for {} hit points.", self.name, target.name, dealt), MessageCategory::Combat);
+            game.last_attacker = Some(self.name.clone());
+
+            if let Some(xp) = target.take_damage(damage, damage_type, Some(self.pos()), game) {
+                self.fighter.as_mut().unwrap().xp += xp;
+            }
+            if damage_type == DamageType::Cold && target.alive && target.resisted_damage(damage, damage_type) > 0 {
+                target.add_effect(StatusEffect { kind: EffectKind::Slow, amount: SLOW_SPEED, turns: CHILL_TURNS });
+                game.log.add_categorized(format!("The cold slows {} down.", target.name), MessageCategory::Combat);
+            }
+
+        } else if damage < 0 {
+            game.log.add_categorized(format!("{} miss {}.", self.name, target.name), MessageCategory::Combat);
+        } else {
+            game.log.add_categorized(format!("{} attacks {} but it has no effect!",self.name, target.name), MessageCategory::Combat);
+        }
+
+        if target.alive && target.is_unaware() {
+            match target.ai {
+                Some(Ai::Sleeping) => target.wake_up(game),
+                _ => target.ai = Some(Ai::Basic { last_known: Some(self.pos()), turns: MONSTER_CHASE_TURNS }),
+            }
+        }
+    }
+
+    pub fn is_remains(&self) -> bool {
+        self.fighter.is_none() && self.name.starts_with(REMAINS_PREFIX)
+    }
+
+    // remains go under everything, then other floor features, items, and creatures on top
+    pub fn draw_layer(&self) -> u8 {
+        if self.blocks {
+            3
+        } else if self.item.is_some() {
+            2
+        } else if self.is_remains() {
+            0
+        } else {
+            1
+        }
+    }
+
+    pub fn is_hostile(&self) -> bool {
+        self.ai.is_some_and(|ai| ai.is_hostile())
+    }
+
+    // sleeping monsters, and those that haven't spotted the player yet
+    pub fn is_unaware(&self) -> bool {
+        matches!(self.ai, Some(Ai::Sleeping) | Some(Ai::Basic { last_known: None, .. }))
+    }
+
+    pub fn cast(&mut self, cast_type: &str, amount: i32, game: &mut Game) {
+        let max_hp = self.max_hp(game);
+        match cast_type {
+
+            "heal" =>
+
+                if let Some(ref mut fighter) = self.fighter {
+                    fighter.hp += amount;
+                    if fighter.hp > max_hp {
+                        fighter.hp = max_hp;
+                    }
+                }
+
+            "attack_buff" =>
+
+                if let Some(ref mut fighter) = self.fighter {
+                    fighter.base_power = cmp::min(fighter.base_power + amount, PLAYER_MAX_ATTACK);
+                }
+
+            _ => ()
+
+        }
+    }
+
+    pub fn equip (&mut self, log: &mut Messages ){
+
+        if self.item.is_none(){
+            log.add(
+                format!("Can't equip {:?} because it's not an Item.", self),
+                colors::RED,
+            );
+            return;
+        }
+
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                self.identified = true;
+                log.add(
+                    format!("Equipped {} on {:?}.", self.name, equipment.slot),
+                    colors::LIGHT_GREEN,
+                );
+            }
+        } else {
+            log.add(
+                format!("Can't equip {:?} because it's not an Equipment.", self),
+                colors::RED,
+            );
+        }
+    }
+
+    // returns false when a curse keeps the item equipped
+    pub fn dequip(&mut self, log: &mut Messages) -> bool {
+        if self.item.is_none() {
+            log.add(
+                format!("Can't unequip {:?} because it's not an Item.", self),
+                colors::RED,
+            );
+            return true;
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped && equipment.cursed {
+                log.add(
+                    format!("You try to remove the {}, but it appears to be stuck!", self.name),
+                    colors::RED,
+                );
+                return false;
+            }
+            if equipment.equipped {
+                equipment.equipped = false;
+                log.add(
+                    format!("Unequipped {} from {:?}.", self.name, equipment.slot),
+                    colors::LIGHT_YELLOW,
+                );
+            }
+        } else {
+            log.add(
+                format!("Can't unequip {:?} because it's not an Equipment.", self),
+                colors::RED,
+            );
+        }
+        true
+    }
+
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.power_bonus)
+            .sum();
+        base_power + bonus + self.effect_total(EffectKind::Strength)
+    }
+
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.defense_bonus)
+            .sum();
+        base_defense + bonus + self.effect_total(EffectKind::Stoneskin)
+    }
+
+    pub fn effect_total(&self, kind: EffectKind) -> i32 {
+        self.effects.iter().filter(|e| e.kind == kind).map(|e| e.amount).sum()
+    }
+
+    pub fn has_effect(&self, kind: EffectKind) -> bool {
+        self.effects.iter().any(|e| e.kind == kind)
+    }
+
+    // how much energy it banks a turn, never so slow that it stops acting altogether
+    pub fn speed(&self) -> i32 {
+        let base_speed = self.fighter.map_or(NORMAL_SPEED, |f| f.speed);
+        cmp::max(1, base_speed + self.effect_total(EffectKind::Haste) - self.effect_total(EffectKind::Slow))
+    }
+
+    // anything standing on a chasm drops through it, unless it's floating
+    pub fn over_chasm(&self, map: &Map) -> bool {
+        map[self.x as usize][self.y as usize].chasm && !self.has_effect(EffectKind::Levitation)
+    }
+
+    // drinking a second potion refreshes the first rather than stacking
+    pub fn add_effect(&mut self, effect: StatusEffect) {
+        match self.effects.iter_mut().find(|e| e.kind == effect.kind) {
+            Some(existing) => *existing = effect,
+            None => self.effects.push(effect),
+        }
+    }
+
+    // a rough read on how many blows it would take this monster to bring the player down
+    pub fn threat_to(&self, player: &Object, game: &Game) -> &'static str {
+        let damage = self.power(game) - player.defense(game);
+        let player_hp = player.fighter.map_or(0, |f| f.hp);
+        if damage <= 0 {
+            return "harmless";
+        }
+        match (player_hp + damage - 1) / damage {
+            0..=3 => "deadly",
+            4..=8 => "dangerous",
+            _ => "weak",
+        }
+    }
+
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.name == "player" {
+            game.inventory
+                .iter()
+                .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+                .map(|item| item.equipment.unwrap())
+                .collect()
+        } else {
+            vec![] //noequippement objects
+        }
+    }
+
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.max_hp_bonus)
+            .sum();
+        base_max_hp + bonus
+    }
+
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub blocked: bool,
+    pub block_sight: bool,
+    pub explored: bool,
+    #[serde(default)]
+    pub trap: Option<Trap>,
+    // looks like open floor, but whatever walks onto it falls to the level below
+    #[serde(default)]
+    pub chasm: bool,
+}
+
+impl Tile {
+    pub fn empty() -> Self{
+        Tile{blocked: false, block_sight: false, explored: false, trap: None, chasm: false}
+    }
+
+    pub fn wall() -> Self{
+        Tile{blocked: true, block_sight: true, explored: false, trap: None, chasm: false}
+    }
+
+    // undergrowth hides what's behind it but can be pushed through
+    pub fn thicket() -> Self {
+        Tile { blocked: false, block_sight: true, explored: false, trap: None, chasm: false }
+    }
+
+    // deep water can be seen and shot across but not waded
+    pub fn pool() -> Self {
+        Tile { blocked: true, block_sight: false, explored: false, trap: None, chasm: false }
+    }
+
+    pub fn chasm() -> Self {
+        Tile { blocked: false, block_sight: false, explored: false, trap: None, chasm: true }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    Spikes,
+    Fire,
+}
+
+impl TrapKind {
+    pub const ALL: &'static [TrapKind] = &[TrapKind::Spikes, TrapKind::Fire];
+
+    pub fn damage(self) -> (i32, DamageType) {
+        match self {
+            TrapKind::Spikes => (10, DamageType::Physical),
+            TrapKind::Fire => (8, DamageType::Fire),
+        }
+    }
+}
+
+impl std::fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            TrapKind::Spikes => write!(f, "spike trap"),
+            TrapKind::Fire => write!(f, "fire trap"),
+        }
+    }
+}
+
+// hidden until searched out or stepped on, and it stays armed either way
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub found: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Item {
+    Heal,
+    AttackBuff,
+    Lightning,
+    Sword,
+    Chest,
+    Targe,
+    Greatsword,
+    PowerRing,
+    VigorRing,
+    IdentifyScroll,
+    RemoveCurse,
+    Arrow,
+    Ration,
+    MapScroll,
+    SummonAlly,
+    LightningWand,
+    Recharge,
+    StrengthPotion,
+    StoneskinPotion,
+    Clairvoyance,
+    HastePotion,
+    LevitationPotion,
+}
+
+impl Item {
+    pub const ALL: &'static [Item] = &[
+        Item::Heal,
+        Item::AttackBuff,
+        Item::Lightning,
+        Item::Sword,
+        Item::Chest,
+        Item::Targe,
+        Item::Greatsword,
+        Item::PowerRing,
+        Item::VigorRing,
+        Item::IdentifyScroll,
+        Item::RemoveCurse,
+        Item::Arrow,
+        Item::Ration,
+        Item::MapScroll,
+        Item::SummonAlly,
+        Item::LightningWand,
+        Item::Recharge,
+        Item::StrengthPotion,
+        Item::StoneskinPotion,
+        Item::Clairvoyance,
+        Item::HastePotion,
+        Item::LevitationPotion,
+    ];
+
+    pub fn is_potion(self) -> bool {
+        matches!(
+            self,
+            Item::Heal | Item::StrengthPotion | Item::StoneskinPotion | Item::HastePotion | Item::LevitationPotion
+        )
+    }
+
+    pub fn is_scroll(self) -> bool {
+        matches!(
+            self,
+            Item::AttackBuff
+                | Item::Lightning
+                | Item::IdentifyScroll
+                | Item::RemoveCurse
+                | Item::MapScroll
+                | Item::SummonAlly
+                | Item::Recharge
+                | Item::Clairvoyance
+        )
+    }
+
+    pub fn max_charges(self) -> Option<i32> {
+        match self {
+            Item::LightningWand => Some(WAND_CHARGES),
+            _ => None,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Item::Heal => "restores health",
+            Item::AttackBuff => "permanently raises attack",
+            Item::Lightning => "strikes the nearest enemy",
+            Item::Sword | Item::Greatsword => "a weapon",
+            Item::Chest | Item::Targe => "armour",
+            Item::PowerRing | Item::VigorRing => "a ring",
+            Item::IdentifyScroll => "reveals what an item is",
+            Item::RemoveCurse => "lifts curses from your gear",
+            Item::Arrow => "ammunition for shooting",
+            Item::Ration => "food",
+            Item::MapScroll => "maps the whole level",
+            Item::SummonAlly => "calls an ally to your side",
+            Item::LightningWand => "zaps the nearest enemy",
+            Item::Recharge => "restores a wand's charges",
+            Item::StrengthPotion => "raises attack for a while",
+            Item::StoneskinPotion => "raises defense for a while",
+            Item::HastePotion => "lets you act twice as often for a while",
+            Item::LevitationPotion => "floats you safely over chasms",
+            Item::Clairvoyance => "shows a distant spot, monsters and all",
+        }
+    }
+
+    // what the built-in spawn table says about each item
+    fn default_lore(self) -> &'static str {
+        match self {
+            Item::Heal => "A violet draught brewed by the hermits of the upper caves. Drinking it closes wounds and restores a good deal of health.",
+            Item::AttackBuff => "A battle hymn copied out by a long-dead warlord. Reading it aloud makes you a permanently better fighter, though there is only so much it can teach.",
+            Item::Lightning => "Reads a scroll that strikes the nearest foe with lightning. Only works if something hostile is close by and in clear view.",
+            Item::Sword => "A plain, well-balanced blade held in the right hand.",
+            Item::Greatsword => "A heavy blade that needs both hands, hitting far harder than a sword at the cost of a shield.",
+            Item::Chest => "Interlocking iron rings that turn blows aside and let you take more punishment.",
+            Item::Targe => "A small round shield strapped to the left arm.",
+            Item::PowerRing => "A gold band that lends its wearer a little extra strength. Two rings can be worn at once.",
+            Item::VigorRing => "A red-stoned band that keeps its wearer on their feet a little longer. Two rings can be worn at once.",
+            Item::IdentifyScroll => "Tells you exactly what one item in your pack is, and from then on every item like it.",
+            Item::RemoveCurse => "Breaks the curse on everything you are wearing so that it can be taken off.",
+            Item::Arrow => "Fletched arrows for shooting at a monster in view. Each one is lost once it is loosed.",
+            Item::Ration => "Dried meat and hard bread. Eating it staves off hunger for a good while.",
+            Item::MapScroll => "An old surveyor's chart that draws the whole level in your mind, though not what lives in it.",
+            Item::SummonAlly => "Calls a companion out of thin air to fight at your side for a while.",
+            Item::LightningWand => "A rod that hums with stored storms. Each zap strikes the nearest foe, and it can be recharged once empty.",
+            Item::Recharge => "Fills a spent wand with fresh charges.",
+            Item::StrengthPotion => "A bitter orange brew that makes you hit harder for a few turns.",
+            Item::StoneskinPotion => "Turns your skin to grey stone for a few turns, making you much harder to hurt.",
+            Item::HastePotion => "A fizzing yellow tonic that quickens you, so monsters only get half as many moves for each of yours until it wears off.",
+            Item::LevitationPotion => "A pale, weightless draught. For a while your feet never quite touch the ground, and chasms can be crossed without falling in.",
+            Item::Clairvoyance => "Lets you look at any spot you have explored as though you stood there, monsters included.",
+        }
+    }
+
+    // scarce enough that the front-end asks before one gets used up
+    pub fn is_rare(self) -> bool {
+        matches!(
+            self,
+            Item::AttackBuff | Item::Lightning | Item::SummonAlly | Item::MapScroll | Item::Recharge | Item::Clairvoyance
+        )
+    }
+
+    // only potions and scrolls start unidentified
+    pub fn is_magic(self) -> bool {
+        self.is_potion() || self.is_scroll()
+    }
+
+    // always worth having, so auto-pickup grabs it without asking
+    pub fn is_ammo(self) -> bool {
+        matches!(self, Item::Arrow)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ItemCategory {
+    Equipment,
+    Consumable,
+    Misc,
+}
+
+pub enum UseResult {
+    UsedUp,
+    Cancelled,
+    UseAndKept,
+    UseAndTakeTurn
+}
+
+pub type Map = Vec<Vec<Tile>>;
+
+pub fn map_size(map: &Map) -> (i32, i32) {
+    (map.len() as i32, map[0].len() as i32)
+}
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub text: String,
+    pub color: Color,
+    #[serde(default)]
+    pub turn: u64,
+    #[serde(default)]
+    pub category: Option<MessageCategory>,
+}
+
+impl LogEntry {
+    pub fn color_in(&self, palette: &Palette) -> Color {
+        self.category.map_or(self.color, |category| palette.message_color(category))
+    }
+
+    pub fn with_turn(&self) -> String {
+        format!("[T{}] {}", self.turn, self.text)
+    }
+}
+
+// saved as a bare list of entries, which also reads the (text, color) pairs of older saves
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Messages {
+    entries: Vec<LogEntry>,
+    // stamped on new entries, kept in step with Game::turns
+    #[serde(skip)]
+    pub turn: u64,
+}
+
+impl Messages {
+    pub fn iter(&self) -> std::slice::Iter<'_, LogEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+
+pub fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut[Object]){
+
+    let (x,y) = objects[id].pos();
+
+    if footprint_fits(x + dx, y + dy, objects[id].size, map, objects, Some(id)) {
+        objects[id].set_pos(x + dx, y + dy);
+    }
+
+}
+
+// every tile a creature of this size would cover at (x, y) is open, the mover itself aside
+pub fn footprint_fits(x: i32, y: i32, size: i32, map: &Map, objects: &[Object], mover: Option<usize>) -> bool {
+    let (map_width, map_height) = map_size(map);
+    (x..x + size).all(|cell_x| {
+        (y..y + size).all(|cell_y| {
+            cell_x >= 0 && cell_y >= 0 && cell_x < map_width && cell_y < map_height
+                && !map[cell_x as usize][cell_y as usize].blocked
+                && !objects
+                    .iter()
+                    .enumerate()
+                    .any(|(id, object)| Some(id) != mover && object.blocks && object.occupies(cell_x, cell_y))
+        })
+    })
+}
+
+fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
+pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    // already there, and normalising a zero vector would give NaN
+    if (dx, dy) == (0, 0) {
+        return;
+    }
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    let dx = ((dx as f32 / distance).round() as i32).clamp(-1, 1);
+    let dy = ((dy as f32 / distance).round() as i32).clamp(-1, 1);
+
+    move_by(id, dx, dy, map, objects);
+}
+
+// like move_towards, but a careful monster takes the cheapest way round traps and chasms;
+// reckless ones and anything too big to fit the single-tile path still head straight in
+pub fn walk_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+    let careful = objects[id].fighter.is_none_or(|f| !f.reckless) && objects[id].size == 1;
+    let step = if careful { cheapest_step(map, objects[id].pos(), (target_x, target_y), tile_cost) } else { None };
+    match step {
+        Some((dx, dy)) => move_by(id, dx, dy, map, objects),
+        None => move_towards(id, target_x, target_y, map, objects),
+    }
+}
+
+pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert_ne!(first_index, second_index);
+
+    let split_at_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+pub type UseFn = fn(usize, &mut [Object], &mut Game) -> UseResult;
+
+// what using an item does, kept apart from Item so a spawns file can say which one an item gets
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum UseCallback {
+    Heal,
+    AttackBuff,
+    Equip,
+    RemoveCurse,
+    Eat,
+    Mapping,
+    SummonAlly,
+    Strength,
+    Stoneskin,
+    Haste,
+    Levitate,
+    Lightning,
+    Identify,
+    Recharge,
+    Clairvoyance,
+    Shoot,
+}
+
+impl UseCallback {
+    // the ones that need a target or a menu are handled by the front-end
+    pub fn callback(self) -> Option<UseFn> {
+        use UseCallback::*;
+        let callback: UseFn = match self {
+            Heal => cast_heal,
+            AttackBuff => cast_attack_buff,
+            Equip => toggle_equipment,
+            RemoveCurse => cast_remove_curse,
+            Eat => cast_eat,
+            Mapping => cast_mapping,
+            SummonAlly => cast_summon_ally,
+            Strength => cast_strength,
+            Stoneskin => cast_stoneskin,
+            Haste => cast_haste,
+            Levitate => cast_levitate,
+            Lightning | Identify | Recharge | Clairvoyance | Shoot => return None,
+        };
+        Some(callback)
+    }
+}
+
+pub fn item_use(item: Item) -> Option<UseCallback> {
+    with_spawn_table(|spawns| spawns.item(item).and_then(|template| template.on_use))
+}
+
+pub fn item_lore(item: Item) -> String {
+    with_spawn_table(|spawns| spawns.item(item).map_or(String::new(), |template| template.lore.clone()))
+}
+
+// the effect of using an item that needs no targeting or menu; the front-end handles the others
+pub fn apply_item(item: Item, inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    match item_use(item).and_then(UseCallback::callback) {
+        Some(on_use) => on_use(inventory_id, objects, game),
+        None => UseResult::Cancelled,
+    }
+}
+
+pub fn cast_heal(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult{
+
+    let player = &mut objects[PLAYER];
+
+    if let Some(fighter) = player.fighter {
+        if fighter.hp == player.max_hp(game) {
+            game.log.add("You are already at full health.", colors::RED);
+            return UseResult::Cancelled;
+        }
+        game.log
+            .add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
+        objects[PLAYER].cast("heal", HEAL_AMOUNT, game);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+pub fn cast_attack_buff(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult{
+
+    if let Some(fighter) = objects[PLAYER].fighter {
+        if fighter.base_power >= PLAYER_MAX_ATTACK {
+            game.log.add("Your attack lvl is too high for this item level", colors::RED);
+            return UseResult::Cancelled;
+        }
+        objects[PLAYER].cast("attack_buff", ATTACK_BUFF, game);
+        let gained = objects[PLAYER].fighter.map_or(0, |f| f.base_power) - fighter.base_power;
+        game.log.add(format!("Permanently increase your attack by: {}", gained), colors::GREEN);
+
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+pub fn cast_strength(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    objects[PLAYER].add_effect(StatusEffect { kind: EffectKind::Strength, amount: POTION_BUFF, turns: POTION_BUFF_TURNS });
+    game.log.add("You feel a surge of strength!", colors::LIGHT_GREEN);
+    UseResult::UsedUp
+}
+
+pub fn cast_stoneskin(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    objects[PLAYER].add_effect(StatusEffect { kind: EffectKind::Stoneskin, amount: POTION_BUFF, turns: POTION_BUFF_TURNS });
+    game.log.add("Your skin hardens like stone.", colors::LIGHT_GREEN);
+    UseResult::UsedUp
+}
+
+pub fn cast_haste(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    objects[PLAYER].add_effect(StatusEffect { kind: EffectKind::Haste, amount: HASTE_SPEED, turns: HASTE_TURNS });
+    game.log.add("The world around you slows to a crawl!", colors::YELLOW);
+    UseResult::UsedUp
+}
+
+pub fn cast_levitate(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    objects[PLAYER].add_effect(StatusEffect { kind: EffectKind::Levitation, amount: 0, turns: LEVITATION_TURNS });
+    game.log.add("You float gently off the ground.", colors::LIGHTEST_SKY);
+    UseResult::UsedUp
+}
+
+pub fn cast_eat(_inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
+    if game.nutrition >= MAX_NUTRITION {
+        game.log.add("You are too full to eat anything.", colors::RED);
+        return UseResult::Cancelled;
+    }
+    game.nutrition = cmp::min(game.nutrition + RATION_NUTRITION, MAX_NUTRITION);
+    game.log.add("That food really hit the spot!", colors::LIGHT_GREEN);
+    UseResult::UseAndTakeTurn
+}
+
+pub fn cast_mapping(_inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
+    for tile in game.map.iter_mut().flat_map(|column| column.iter_mut()) {
+        tile.explored = true;
+    }
+    game.log.add("An image of your surroundings forms in your mind.", colors::LIGHT_CYAN);
+    UseResult::UsedUp
+}
+
+pub fn cast_summon_ally(_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let free_tile = (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (player_x + dx, player_y + dy)))
+        .find(|&(x, y)| {
+            !is_blocked(x, y, &game.map, objects)
+                && !game.pending_objects.iter().any(|o| o.pos() == (x, y))
+        });
+
+    let (x, y) = match free_tile {
+        Some(tile) => tile,
+        None => {
+            game.log.add("There is no room for anything to appear.", colors::RED);
+            return UseResult::Cancelled;
+        }
+    };
+
+    let mut ally = Object::new(x, y, 'w', "spirit wolf", colors::LIGHT_CYAN, true);
+    ally.fighter = Some(Fighter {
+        base_max_hp: 20,
+        hp: 20,
+        base_defense: 1,
+        base_power: 4 + game.dungeon_level as i32 / 2,
+        on_death: DeathCallback::Monster,
+        damage_type: DamageType::Physical,
+        resistances: Resistances::default(),
+        mana: 0,
+        max_mana: 0,
+        speed: NORMAL_SPEED,
+        energy: 0,
+        perception: PERCEPTION_RADIUS,
+        reckless: false,
+        xp: 0,
+    });
+    ally.ai = Some(Ai::Allied { lifetime: ALLY_LIFETIME });
+    ally.alive = true;
+    game.log.add("A spirit wolf answers your call!", colors::LIGHT_CYAN);
+    game.pending_objects.push(ally);
+    UseResult::UseAndTakeTurn
+}
+
+pub fn hunger_status(nutrition: i32) -> (&'static str, Color) {
+    if nutrition <= 0 {
+        ("Starving", colors::RED)
+    } else if nutrition <= HUNGRY_NUTRITION {
+        ("Hungry", colors::YELLOW)
+    } else {
+        ("Not hungry", colors::LIGHT_GREEN)
+    }
+}
+
+pub fn toggle_equipment(inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+
+    if equipment.equipped {
+        game.inventory[inventory_id].dequip(&mut game.log);
+    }else{
+        let current = get_equipped_in_slot(equipment.slot, &game.inventory);
+        // look for a stuck item first so a curse doesn't leave the other hand half undressed
+        let stuck = current
+            .iter()
+            .find(|&&id| game.inventory[id].equipment.is_some_and(|e| e.cursed));
+        if let Some(&stuck) = stuck {
+            game.inventory[stuck].dequip(&mut game.log);
+            return UseResult::UseAndKept;
+        }
+        for id in current {
+            game.inventory[id].dequip(&mut game.log);
+        }
+        game.inventory[inventory_id].equip(&mut game.log);
+        if equipment.cursed {
+            game.log.add("A malevolent chill runs through you...", colors::DARK_VIOLET);
+        }
+    }
+
+    UseResult::UseAndKept
+}
+
+pub fn cast_remove_curse(_inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
+    let mut uncursed = 0;
+    for equipment in game.inventory.iter_mut().filter_map(|item| item.equipment.as_mut()) {
+        if equipment.equipped && equipment.cursed {
+            equipment.cursed = false;
+            uncursed += 1;
+        }
+    }
+
+    if uncursed > 0 {
+        game.log.add("You feel as if someone is watching over you.", colors::LIGHT_CYAN);
+    } else {
+        game.log.add("You feel a faint warmth, then nothing.", colors::LIGHT_GREY);
+    }
+    UseResult::UsedUp
+}
+
+// how the item's bonuses compare with what is worn in its slot
+pub fn equipment_comparison(item: &Object, game: &Game) -> String {
+    let equipment = match item.equipment {
+        Some(equipment) => equipment,
+        None => return String::new(),
+    };
+    if !item.identified {
+        return "bonuses unknown until worn".into();
+    }
+    let (power, defense, max_hp) = get_equipped_in_slot(equipment.slot, &game.inventory)
+        .into_iter()
+        .filter_map(|id| game.inventory[id].equipment)
+        .fold((0, 0, 0), |(power, defense, max_hp), c| {
+            (power + c.power_bonus, defense + c.defense_bonus, max_hp + c.max_hp_bonus)
+        });
+
+    let mut parts = vec![
+        format!("{:+} power (now {:+})", equipment.power_bonus, power),
+        format!("{:+} defense (now {:+})", equipment.defense_bonus, defense),
+    ];
+    if equipment.max_hp_bonus != 0 || max_hp != 0 {
+        parts.push(format!("{:+} hp (now {:+})", equipment.max_hp_bonus, max_hp));
+    }
+    parts.join(", ")
+}
+
+// one line per worn item with what it adds, for the character and level-up screens
+pub fn equipment_breakdown(game: &Game) -> Vec<String> {
+    game.inventory
+        .iter()
+        .filter_map(|item| item.equipment.filter(|e| e.equipped).map(|e| (item, e)))
+        .map(|(item, equipment)| {
+            let bonuses: Vec<String> = [
+                (equipment.power_bonus, "attack"),
+                (equipment.defense_bonus, "def"),
+                (equipment.max_hp_bonus, "hp"),
+            ]
+            .iter()
+            .filter(|&&(bonus, _)| bonus != 0)
+            .map(|&(bonus, stat)| format!("{:+} {}", bonus, stat))
+            .collect();
+            let bonuses = if bonuses.is_empty() { "nothing".to_string() } else { bonuses.join(", ") };
+            format!("{}: {}", game.display_name(item), bonuses)
+        })
+        .collect()
+}
+
+// everything worn that would have to come off to free the slot
+pub fn get_equipped_in_slot (slot: Slot, inventory: &[Object]) -> Vec<usize> {
+    let mut worn: Vec<usize> = inventory
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.equipment.as_ref().is_some_and(|e| e.equipped && e.slot.overlaps(slot)))
+        .map(|(inventory_id, _)| inventory_id)
+        .collect();
+    // a slot with room to spare, like a free finger, doesn't need emptying
+    let excess = (worn.len() + 1).saturating_sub(slot.capacity());
+    worn.truncate(excess);
+    worn
+}
+
+// whatever is held in the weapon hand, one-handed or not
+pub fn equipped_weapon(inventory: &[Object]) -> Option<usize> {
+    inventory.iter().position(|item| {
+        item.equipment.is_some_and(|e| e.equipped && e.slot.overlaps(Slot::RightHand))
+    })
+}
+
+// the weapon hits for its power bonus and then lies at the target's feet until picked up again
+pub fn throw_weapon(inventory_id: usize, target_id: usize, objects: &mut Vec<Object>, game: &mut Game) -> bool {
+    if !game.inventory[inventory_id].dequip(&mut game.log) {
+        return false;
+    }
+    let mut weapon = game.inventory.remove(inventory_id);
+    let damage = weapon.equipment.map_or(0, |e| e.power_bonus);
+    game.log.add(
+        format!(
+            "You hurl your {} at the {} for {} hit points.",
+            weapon.name,
+            objects[target_id].name,
+            objects[target_id].resisted_damage(damage, DamageType::Physical)
+        ),
+        colors::LIGHT_CYAN,
+    );
+    let (x, y) = objects[target_id].pos();
+    let thrower = objects[PLAYER].pos();
+    if let Some(xp) = objects[target_id].take_damage(damage, DamageType::Physical, Some(thrower), game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+    weapon.set_pos(x, y);
+    objects.push(weapon);
+    true
+}
+
+pub fn can_level_up(player: &Object) -> bool {
+    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+    player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp
+}
+
+// stat is 0 for constitution, 1 for strength, 2 for agility
+pub fn gain_level(player: &mut Object, stat: usize, game: &mut Game) {
+    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+    player.level += 1;
+    game.log.add_categorized(format!("You reached level {}!", player.level), MessageCategory::Level);
+
+    let fighter = player.fighter.as_mut().unwrap();
+    fighter.xp -= level_up_xp;
+    match stat {
+        0 => {
+            fighter.base_max_hp += 20;
+            fighter.hp += 20;
+        }
+        1 => {
+            fighter.base_power += 1;
+        }
+        2 => {
+            fighter.base_defense += 1;
+        }
+        _ => unreachable!(),
+    }
+}
+
+pub fn closest_monster(max_range: i32, objects: &mut [Object], map: &Map, fov_map: &impl Visibility) -> Option<usize> {
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range + 1) as f32;
+    let player = objects[PLAYER].pos();
+
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER)
+            && object.fighter.is_some()
+            && object.is_hostile()
+            && fov_map.is_in_fov(object.x, object.y)
+            && has_line_of_sight(map, player, object.pos())
+        {
+            let dist = objects[PLAYER].distance_to(object);
+            if dist < closest_dist {
+                closest_enemy = Some(id);
+                closest_dist = dist;
+            }
+        }
+    }
+    closest_enemy
+}
+
+pub fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &impl Visibility) {
+
+    use Ai::*;
+    species_quirks(monster_id, objects, game, fov_map);
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Basic { last_known, turns } => ai_basic(monster_id, objects, fov_map, game, last_known, turns),
+            Sleeping => ai_sleeping(monster_id, objects, game),
+            Boss { seen, summon_cooldown } => ai_boss(monster_id, objects, fov_map, game, seen, summon_cooldown),
+            Allied { lifetime } => ai_allied(monster_id, objects, fov_map, game, lifetime),
+        };
+        objects[monster_id].ai = Some(new_ai);
+    }
+}
+
+// trolls knit their wounds shut, orcs fly into a rage when badly hurt
+fn species_quirks(monster_id: usize, objects: &mut [Object], game: &mut Game, fov_map: &impl Visibility) {
+    let max_hp = objects[monster_id].max_hp(game);
+    let monster = &mut objects[monster_id];
+    let seen = fov_map.is_in_fov(monster.x, monster.y);
+    let hp = match monster.fighter {
+        Some(fighter) => fighter.hp,
+        None => return,
+    };
+
+    match monster.name.as_str() {
+        "troll" if hp < max_hp => {
+            if let Some(ref mut fighter) = monster.fighter {
+                fighter.hp = cmp::min(hp + TROLL_REGENERATION, max_hp);
+            }
+            if seen {
+                game.log.add_categorized("The troll's wounds close before your eyes.", MessageCategory::Danger);
+            }
+        }
+        "orc" if hp * 2 < max_hp && monster.effect_total(EffectKind::Strength) == 0 => {
+            monster.add_effect(StatusEffect { kind: EffectKind::Strength, amount: ORC_RAGE_POWER, turns: ORC_RAGE_TURNS });
+            if seen {
+                game.log.add_categorized("The orc roars and flies into a rage!", MessageCategory::Danger);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn ai_basic(
+    monster_id: usize,
+    objects: &mut [Object],
+    fov_map: &impl Visibility,
+    game: &mut Game,
+    last_known: Option<(i32, i32)>,
+    turns: i32,
+) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if fov_map.is_in_fov(monster_x, monster_y) && (last_known.is_some() || notices_player(monster_id, objects, game)) {
+        approach_and_attack(monster_id, objects, game);
+        return Ai::Basic {
+            last_known: Some(objects[PLAYER].pos()),
+            turns: MONSTER_CHASE_TURNS,
+        };
+    }
+
+    // out of sight, head for where the player was last seen for a while
+    match last_known {
+        Some((x, y)) if turns > 0 && (monster_x, monster_y) != (x, y) => {
+            walk_towards(monster_id, x, y, &game.map, objects);
+            Ai::Basic { last_known, turns: turns - 1 }
+        }
+        _ => Ai::Basic { last_known: None, turns: 0 },
+    }
+}
+
+// a monster that hasn't spotted the player only does once they come within earshot
+fn notices_player(monster_id: usize, objects: &[Object], game: &Game) -> bool {
+    let perception = objects[monster_id].fighter.map_or(PERCEPTION_RADIUS, |f| f.perception);
+    objects[monster_id].distance_to(&objects[PLAYER]) <= perception * player_noise(game)
+}
+
+// walking about at full pace is the only thing loud enough to carry the whole way
+pub fn player_noise(game: &Game) -> f32 {
+    if game.player_moved && !game.sneaking {
+        1.0
+    } else {
+        STILL_NOISE
+    }
+}
+
+// sneaking and a heavy pack slow the player down on top of whatever else affects their speed
+pub fn player_speed(objects: &[Object], game: &Game) -> i32 {
+    let mut penalty = if game.sneaking { SNEAK_SPEED_PENALTY } else { 0 };
+    if is_burdened(objects, game) {
+        penalty += BURDENED_SPEED_PENALTY;
+    }
+    cmp::max(1, objects[PLAYER].speed() - penalty)
+}
+
+fn ai_sleeping(monster_id: usize, objects: &mut [Object], game: &mut Game) -> Ai {
+    if objects[monster_id].distance_to(&objects[PLAYER]) <= WAKE_RADIUS * player_noise(game) {
+        objects[monster_id].wake_up(game);
+        return Ai::Basic { last_known: None, turns: 0 };
+    }
+    Ai::Sleeping
+}
+
+fn ai_allied(
+    ally_id: usize,
+    objects: &mut [Object],
+    fov_map: &impl Visibility,
+    game: &mut Game,
+    lifetime: i32,
+) -> Ai {
+    if lifetime <= 1 {
+        game.log.add(format!("The {} fades away.", objects[ally_id].name), colors::LIGHT_GREY);
+        return Ai::Allied { lifetime: 0 };
+    }
+
+    let enemy = objects
+        .iter()
+        .enumerate()
+        .filter(|&(_, o)| o.fighter.is_some() && o.is_hostile() && fov_map.is_in_fov(o.x, o.y))
+        .map(|(id, o)| (id, objects[ally_id].distance_to(o)))
+        .filter(|&(_, dist)| dist <= ALLY_SIGHT_RANGE)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    match enemy {
+        Some((enemy_id, dist)) if dist < 2.0 => {
+            let (ally, enemy) = mut_two(ally_id, enemy_id, objects);
+            ally.attack(enemy, game);
+        }
+        Some((enemy_id, _)) => {
+            let (x, y) = objects[enemy_id].pos();
+            walk_towards(ally_id, x, y, &game.map, objects);
+        }
+        None if objects[ally_id].distance_to(&objects[PLAYER]) > 2.0 => {
+            let (x, y) = objects[PLAYER].pos();
+            walk_towards(ally_id, x, y, &game.map, objects);
+        }
+        None => {}
+    }
+    Ai::Allied { lifetime: lifetime - 1 }
+}
+
+fn approach_and_attack(monster_id: usize, objects: &mut [Object], game: &mut Game) {
+    if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+        let (player_x, player_y) = objects[PLAYER].pos();
+        walk_towards(monster_id, player_x, player_y, &game.map, objects);
+    } else if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
+        let (monster, player) = mut_two(monster_id, PLAYER, objects);
+        monster.attack(player, game);
+    }
+}
+
+fn ai_boss(
+    monster_id: usize,
+    objects: &mut [Object],
+    fov_map: &impl Visibility,
+    game: &mut Game,
+    seen: bool,
+    summon_cooldown: i32,
+) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if !fov_map.is_in_fov(monster_x, monster_y) {
+        return Ai::Boss { seen, summon_cooldown };
+    }
+
+    if !seen {
+        game.log.add(
+            format!("The ground trembles as the {} rises before you!", objects[monster_id].name),
+            colors::CRIMSON,
+        );
+    }
+
+    if summon_cooldown > 0 {
+        approach_and_attack(monster_id, objects, game);
+        return Ai::Boss { seen: true, summon_cooldown: summon_cooldown - 1 };
+    }
+
+    // summon a minion on the first spot beside the boss with room for the whole of it
+    let mut minion = create_monster("poulet", monster_x, monster_y, game.dungeon_level);
+    let (size, minion_size) = (objects[monster_id].size, minion.size);
+    let free_tile = (monster_x - minion_size..=monster_x + size)
+        .flat_map(|x| (monster_y - minion_size..=monster_y + size).map(move |y| (x, y)))
+        .find(|&(x, y)| {
+            footprint_fits(x, y, minion_size, &game.map, objects, None)
+                && !game.pending_objects.iter().any(|o| {
+                    (x..x + minion_size).any(|cell_x| (y..y + minion_size).any(|cell_y| o.occupies(cell_x, cell_y)))
+                })
+        });
+    match free_tile {
+        Some((x, y)) => {
+            minion.set_pos(x, y);
+            game.difficulty.adjust_monster(&mut minion);
+            game.log.add(
+                format!("The {} calls a {} to its side!", objects[monster_id].name, minion.name),
+                colors::ORANGE,
+            );
+            game.pending_objects.push(minion);
+        }
+        None => {
+            approach_and_attack(monster_id, objects, game);
+        }
+    }
+    Ai::Boss { seen: true, summon_cooldown: BOSS_SUMMON_INTERVAL }
+}
+
+// Bresenham line from one tile to another, without the starting tile
+pub fn line_points(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    let mut points = vec![];
+    while (x, y) != to {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        points.push((x, y));
+    }
+    points
+}
+
+// a clear shot needs every tile between the two ends to be open, creatures don't get in the way
+pub fn has_line_of_sight(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+    let (map_width, map_height) = map_size(map);
+    let path = line_points(from, to);
+    path.iter().take(path.len().saturating_sub(1)).all(|&(x, y)| {
+        x >= 0 && y >= 0 && x < map_width && y < map_height && !map[x as usize][y as usize].block_sight
+    })
+}
+
+pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+
+    if map[x as usize][y as usize].blocked {
+        return true;
+    }
+
+    objects.iter().any(|object |{
+        object.blocks && object.occupies(x, y)
+    })
+
+}
+
+pub fn items_at(pos: (i32, i32), objects: &[Object]) -> Vec<usize> {
+    (0..objects.len())
+        .filter(|&id| objects[id].item.is_some() && objects[id].pos() == pos)
+        .collect()
+}
+
+pub fn carried_weight(game: &Game) -> i32 {
+    game.inventory.iter().map(Object::total_weight).sum()
+}
+
+// the stronger the player, the more they can haul
+pub fn carry_capacity(player: &Object) -> i32 {
+    CARRY_CAPACITY_BASE + player.fighter.map_or(0, |f| f.base_power) * CARRY_CAPACITY_PER_POWER
+}
+
+pub fn is_burdened(objects: &[Object], game: &Game) -> bool {
+    game.difficulty.encumbrance() && carried_weight(game) > carry_capacity(&objects[PLAYER])
+}
+
+pub fn pick_item_up(object_id:usize, objects: &mut Vec<Object>, game: &mut Game){
+    let max_load = carry_capacity(&objects[PLAYER]) * MAX_LOAD_PERCENT / 100;
+    if game.difficulty.encumbrance() && carried_weight(game) + objects[object_id].total_weight() > max_load {
+        game.log.add_categorized(format!("The {} is too heavy to carry with everything else.", objects[object_id].name), MessageCategory::Item);
+        return;
+    }
+    let burdened = is_burdened(objects, game);
+
+    let stack_id = game.inventory.iter().position(|item| item.stacks_with(&objects[object_id]));
+
+    if let Some(stack_id) = stack_id {
+        let item = objects.swap_remove(object_id);
+        let name = game.display_name(&item);
+        game.log.add_categorized(format!("You pick up a {}", name), MessageCategory::Item);
+
+        game.inventory[stack_id].quantity += item.quantity;
+    } else if game.inventory.len() >= INVENTORY_LIMIT {
+        game.log.add_categorized(format!("Your inventory is full, you cannot pick up {}",objects[object_id].name), MessageCategory::Item);
+
+    }else{
+        let item = objects.swap_remove(object_id);
+        let name = game.display_name(&item);
+        game.log.add_categorized(format!("You pick up a {}", name), MessageCategory::Item);
+
+        game.inventory.push(item);
+    }
+
+    if !burdened && is_burdened(objects, game) {
+        game.log.add_categorized("Your pack weighs you down, you move more slowly.", MessageCategory::Item);
+    }
+}
+
+fn pick_up_ammo(objects: &mut Vec<Object>, game: &mut Game) {
+    let ammo = objects
+        .iter()
+        .position(|o| o.pos() == objects[PLAYER].pos() && o.item.is_some_and(Item::is_ammo));
+    if let Some(ammo) = ammo {
+        pick_item_up(ammo, objects, game);
+    }
+}
+
+pub fn consume_item(inventory_id: usize, game: &mut Game) {
+    let item = &mut game.inventory[inventory_id];
+    // charged items spend a charge and stay in the pack even when empty
+    if let Some(ref mut charges) = item.charges {
+        *charges = cmp::max(*charges - 1, 0);
+    } else if item.quantity > 1 {
+        item.quantity -= 1;
+    } else {
+        game.inventory.remove(inventory_id);
+    }
+}
+
+// drops keep to one item a tile since only one glyph shows there; loot can still pile up, and
+// the pickup menu sorts that out. the front end asks first so worn gear isn't taken off for nothing
+pub fn item_underfoot(objects: &[Object]) -> bool {
+    let player_pos = objects[PLAYER].pos();
+    objects.iter().any(|object| object.item.is_some() && object.pos() == player_pos)
+}
+
+pub fn drop_item(inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game){
+
+    if item_underfoot(objects) {
+        game.log.add_categorized("There is already something lying here.", MessageCategory::Item);
+        return;
+    }
+    if game.inventory[inventory_id].equipment.is_some() && !game.inventory[inventory_id].dequip(&mut game.log) {
+        return;
+    }
+    let mut item = game.inventory.remove(inventory_id);
+    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    let name = game.display_name(&item);
+    game.log.add_categorized(format!("You dropped a {}", name), MessageCategory::Item);
+    objects.push(item);
+}
+
+// empties the pack of everything not worn onto free floor round the player, nearest first;
+// returns how many items went down
+pub fn drop_all(objects: &mut Vec<Object>, game: &mut Game) -> usize {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let (map_width, map_height) = map_size(&game.map);
+    let mut spots: Vec<(i32, i32)> = (player_x - DROP_SCATTER_RADIUS..=player_x + DROP_SCATTER_RADIUS)
+        .flat_map(|x| (player_y - DROP_SCATTER_RADIUS..=player_y + DROP_SCATTER_RADIUS).map(move |y| (x, y)))
+        .filter(|&(x, y)| x >= 0 && y >= 0 && x < map_width && y < map_height)
+        .filter(|&(x, y)| {
+            let tile = &game.map[x as usize][y as usize];
+            !tile.blocked && !tile.chasm && has_line_of_sight(&game.map, (player_x, player_y), (x, y))
+        })
+        .filter(|&(x, y)| !objects.iter().any(|object| object.item.is_some() && object.pos() == (x, y)))
+        .collect();
+    spots.sort_by_key(|&(x, y)| (x - player_x).pow(2) + (y - player_y).pow(2));
+
+    let mut dropped = 0;
+    let mut spots = spots.into_iter();
+    let mut index = 0;
+    while index < game.inventory.len() {
+        if game.inventory[index].equipment.is_some_and(|e| e.equipped) {
+            index += 1;
+            continue;
+        }
+        let (x, y) = match spots.next() {
+            Some(spot) => spot,
+            None => {
+                game.log.add_categorized("There's no room around you for the rest.", MessageCategory::Item);
+                break;
+            }
+        };
+        let mut item = game.inventory.remove(index);
+        item.set_pos(x, y);
+        objects.push(item);
+        dropped += 1;
+    }
+    if dropped > 0 {
+        game.log.add_categorized(format!("You drop {} item{}.", dropped, if dropped == 1 { "" } else { "s" }), MessageCategory::Item);
+    }
+    dropped
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+
+    pub fn intersect_with(&self, other: &Rect) -> bool {
+        (self.x1 <= other.x2)
+            && (self.x2 >= other.x1)
+            && (self.y1 <= other.y2)
+            && (self.y2 >= other.y1)
+    }
+
+    // true for the carved floor, walls excluded
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x > self.x1 && x < self.x2 && y > self.y1 && y < self.y2
+    }
+
+    // true for the floor and its surrounding walls
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        x >= self.x1 && x <= self.x2 && y >= self.y1 && y <= self.y2
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DeathCallback {
+    Player,
+    Monster,
+    Boss,
+    FinalBoss,
+}
+
+impl DeathCallback {
+    pub fn callback(self, object: &mut Object, game: &mut Game) {
+        use DeathCallback::*;
+        let callback: fn(&mut Object, game: &mut Game) = match self {
+            Player => player_death,
+            Monster => monster_death,
+            Boss => boss_death,
+            FinalBoss => final_boss_death,
+        };
+
+        callback(object, game);
+    }
+}
+
+pub fn cause_of_death(game: &Game) -> String {
+    match game.last_attacker {
+        Some(ref attacker) => format!("killed by {}", attacker),
+        None => "died".to_string(),
+    }
+}
+
+fn player_death(player: &mut Object, game: &mut Game) {
+
+    game.log.add_categorized("You died, see you another time!", MessageCategory::Danger);
+    player.char = '%';
+    player.color = colors::LIGHTER_RED;
+}
+
+fn monster_death(monster: &mut Object, game: &mut Game) {
+
+    game.log.add_categorized(format!("PAF! {} is dead! You gain {}", monster.name, monster.fighter.unwrap().xp), MessageCategory::Combat);
+    monster.char = '%';
+    monster.color = colors::lerp(monster.color, colors::DARK_RED, 0.5);
+    monster.blocks = false;
+    monster.fighter = None;
+    monster.ai = None;
+    monster.name = format!("{}{}", REMAINS_PREFIX, monster.name);
+}
+
+fn final_boss_death(boss: &mut Object, game: &mut Game) {
+    monster_death(boss, game);
+    game.log.add_categorized("The lich crumbles to dust. The dungeon is yours!", MessageCategory::Level);
+    game.won = true;
+}
+
+fn boss_death(boss: &mut Object, game: &mut Game) {
+    let (x, y) = boss.pos();
+    monster_death(boss, game);
+    if boss.guardian {
+        game.log.add_categorized("With its guardian slain, the seal on the stairs breaks.", MessageCategory::Level);
+    }
+
+    let mut loot = create_item(Item::Sword, x, y);
+    loot.name = "kingslayer blade".into();
+    loot.color = colors::GOLD;
+    loot.equipment = Some(Equipment {
+        equipped: false,
+        slot: Slot::RightHand,
+        max_hp_bonus: 10,
+        defense_bonus: 1,
+        power_bonus: 5,
+        cursed: false,
+    });
+    game.log.add("Something glitters among the remains.", colors::GOLD);
+    game.pending_objects.push(loot);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fighter {
+    pub base_max_hp: i32,
+    pub hp: i32,
+    pub base_defense: i32,
+    pub base_power: i32,
+    pub on_death: DeathCallback,
+    pub xp: i32,
+    #[serde(default)]
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub resistances: Resistances,
+    #[serde(default)]
+    pub mana: i32,
+    #[serde(default)]
+    pub max_mana: i32,
+    #[serde(default = "default_speed")]
+    pub speed: i32,
+    #[serde(default)]
+    pub energy: i32,
+    #[serde(default = "default_perception")]
+    pub perception: f32,
+    #[serde(default)]
+    pub reckless: bool,
+}
+
+fn default_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+fn default_perception() -> f32 {
+    PERCEPTION_RADIUS
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Fire,
+    Lightning,
+    Cold,
+}
+
+// percentage of each kind of damage shrugged off, negative for a weakness
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub physical: i32,
+    pub fire: i32,
+    pub lightning: i32,
+    pub cold: i32,
+}
+
+impl Resistances {
+    pub fn against(&self, damage_type: DamageType) -> i32 {
+        match damage_type {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Lightning => self.lightning,
+            DamageType::Cold => self.cold,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Ai {
+    Basic { last_known: Option<(i32, i32)>, turns: i32 },
+    Sleeping,
+    Boss { seen: bool, summon_cooldown: i32 },
+    Allied { lifetime: i32 },
+}
+
+impl Ai {
+    pub fn is_hostile(&self) -> bool {
+        match *self {
+            Ai::Basic { .. } | Ai::Sleeping | Ai::Boss { .. } => true,
+            Ai::Allied { .. } => false,
+        }
+    }
+}
+
+fn create_room(room: Rect, map: &mut Map)
+{
+    for x in (room.x1 + 1)..room.x2{
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+// a roll over the weights, falling back on the first entry if a table that skipped is_valid
+// leaves nothing to roll
+fn weighted_choice(weights: &[u32]) -> usize {
+    WeightedIndex::new(weights).map_or(0, |choice| choice.sample(&mut rng()))
+}
+
+pub fn random_monster(level: u32) -> String {
+    let theme = ThemeKind::for_level(level).theme();
+    with_spawn_table(|spawns| {
+        let weights: Vec<u32> = spawns
+            .monsters
+            .iter()
+            .map(|template| theme.monster_weight(&template.key, from_dungeon_level(&template.weights, level)))
+            .collect();
+        spawns.monsters[weighted_choice(&weights)].key.clone()
+    })
+}
+
+pub fn place_object(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty){
+
+    let max_monsters = with_spawn_table(|spawns| from_dungeon_level(&spawns.max_monsters, level));
+
+    let max_monsters = cmp::max(0, max_monsters as i32 + difficulty.extra_monsters()) as u32;
+    let num_monsters = rng().gen_range(0, max_monsters + 1);
+
+    for _ in 0..num_monsters {
+        let x = rng().gen_range(room.x1 + 1, room.x2);
+        let y = rng().gen_range(room.y1 + 1, room.y2);
+
+
+        if !is_blocked(x, y, map, objects){
+
+            let mut monster = create_monster(&random_monster(level), x, y, level);
+            difficulty.adjust_monster(&mut monster);
+            if let Some(Ai::Basic { .. }) = monster.ai {
+                monster.ai = Some(Ai::Sleeping);
+            }
+            objects.push(monster);
+        }
+
+    }
+
+    let max_items = with_spawn_table(|spawns| from_dungeon_level(&spawns.max_items, level));
+
+    let num_items = rng().gen_range(0, max_items +1);
+
+    for _ in 0..num_items {
+        let x = rng().gen_range(room.x1 +1 , room.x2);
+        let y = rng().gen_range(room.y1 +1 , room.y2);
+
+        if !is_blocked(x, y, map, objects){
+            objects.push(create_item(random_item(level), x, y));
+        }
+    }
+}
+
+fn random_item(level: u32) -> Item {
+    let theme = ThemeKind::for_level(level).theme();
+    with_spawn_table(|spawns| {
+        let weights: Vec<u32> = spawns
+            .items
+            .iter()
+            .map(|template| theme.item_weight(template.item, from_dungeon_level(&template.weights, level)))
+            .collect();
+        spawns.items[weighted_choice(&weights)].item
+    })
+}
+
+pub fn create_item(kind: Item, x: i32, y: i32) -> Object {
+    let template = with_spawn_table(|spawns| spawns.item(kind).cloned())
+        .unwrap_or_else(|| panic!("{:?} has no entry in the spawn table", kind));
+    let mut item = Object::new(x, y, template.glyph, &template.name, template.color, false);
+    item.item = Some(kind);
+    item.equipment = template.equipment;
+    item.weight = template.weight;
+    item.charges = kind.max_charges();
+    let (fewest, most) = template.quantity;
+    if most > fewest {
+        item.quantity = rng().gen_range(fewest, most + 1);
+    }
+    if let Some(ref mut equipment) = item.equipment {
+        if rng().gen::<f32>() < CURSED_CHANCE {
+            equipment.cursed = true;
+            equipment.power_bonus = -equipment.power_bonus;
+            equipment.defense_bonus = -equipment.defense_bonus;
+            equipment.max_hp_bonus = -equipment.max_hp_bonus;
+        }
+    }
+    item.always_visible = true;
+    // gear keeps its bonuses, and any curse, to itself until it is worn or identified
+    item.identified = !item.item.is_some_and(Item::is_magic) && item.equipment.is_none();
+    item
+}
+
+// a copy of a spawnable item to start out wearing, never cursed and already known
+fn starting_gear(kind: Item, slot: Slot) -> Object {
+    let mut item = create_item(kind, 0, 0);
+    item.equipment = with_spawn_table(|spawns| spawns.item(kind).and_then(|template| template.equipment))
+        .map(|equipment| Equipment { equipped: true, slot, ..equipment });
+    item.identified = true;
+    item
+}
+
+pub fn create_monster(name: &str, x: i32, y: i32, level: u32) -> Object {
+    let template = with_spawn_table(|spawns| spawns.monster(name).cloned())
+        .unwrap_or_else(|| panic!("no monster called {} in the spawn table", name));
+    let mut monster = Object::new(x, y, template.glyph, &template.name, template.color, true);
+    monster.fighter = Some(template.fighter(level));
+    monster.ai = Some(template.ai());
+    monster.size = template.size;
+    monster.alive = true;
+    monster
+}
+
+pub struct GeneratedMap {
+    pub map: Map,
+    pub player_start: (i32, i32),
+    pub stairs: (i32, i32),
+    pub rooms: Vec<Rect>,
+}
+
+pub trait MapGenerator {
+    // carves the level and populates it, objects only holds the player on entry
+    fn generate(&self, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, config: &Config) -> GeneratedMap;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    #[default]
+    Rooms,
+    Caves,
+    Bsp,
+}
+
+impl GeneratorKind {
+    // levels cycle through random rooms, partitioned rooms and caves
+    pub fn for_level(level: u32) -> Self {
+        match level % 3 {
+            0 => GeneratorKind::Caves,
+            2 => GeneratorKind::Bsp,
+            _ => GeneratorKind::Rooms,
+        }
+    }
+
+    pub fn generator(self) -> Box<dyn MapGenerator> {
+        match self {
+            GeneratorKind::Rooms => Box::new(RoomsGenerator),
+            GeneratorKind::Caves => Box::new(CaveGenerator),
+            GeneratorKind::Bsp => Box::new(BspGenerator),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: &'static [Difficulty] = &[Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    // how many more (or fewer) monsters a room may hold
+    pub fn extra_monsters(self) -> i32 {
+        match self {
+            Difficulty::Easy => -1,
+            Difficulty::Normal => 0,
+            Difficulty::Hard => 1,
+        }
+    }
+
+    // percentage applied to monster hp and power
+    pub fn monster_strength(self) -> i32 {
+        match self {
+            Difficulty::Easy => 75,
+            Difficulty::Normal => 100,
+            Difficulty::Hard => 125,
+        }
+    }
+
+    // how many turns apart wandering monsters turn up, None for never
+    pub fn wandering_monster_interval(self) -> Option<u64> {
+        match self {
+            Difficulty::Easy => None,
+            Difficulty::Normal => Some(WANDERING_MONSTER_INTERVAL),
+            Difficulty::Hard => Some(WANDERING_MONSTER_INTERVAL * 2 / 3),
+        }
+    }
+
+    // whether the weight of the pack counts for anything
+    pub fn encumbrance(self) -> bool {
+        match self {
+            Difficulty::Easy => false,
+            Difficulty::Normal | Difficulty::Hard => true,
+        }
+    }
+
+    pub fn starting_potions(self) -> u32 {
+        match self {
+            Difficulty::Easy => 2,
+            Difficulty::Normal | Difficulty::Hard => 0,
+        }
+    }
+
+    pub fn adjust_monster(self, monster: &mut Object) {
+        let strength = self.monster_strength();
+        if let Some(ref mut fighter) = monster.fighter {
+            fighter.base_max_hp = cmp::max(1, fighter.base_max_hp * strength / 100);
+            fighter.hp = fighter.base_max_hp;
+            fighter.base_power = fighter.base_power * strength / 100;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Hardcore,
+    Explorer,
+}
+
+impl GameMode {
+    pub const ALL: &'static [GameMode] = &[GameMode::Hardcore, GameMode::Explorer];
+}
+
+impl std::fmt::Display for GameMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            GameMode::Hardcore => write!(f, "Hardcore: death ends the run"),
+            GameMode::Explorer => write!(f, "Explorer: death costs experience"),
+        }
+    }
+}
+
+// repeatable versions of the scroll effects, paid for in mana
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Spell {
+    Lightning,
+    Heal,
+}
+
+impl Spell {
+    pub fn mana_cost(self) -> i32 {
+        match self {
+            Spell::Lightning => 8,
+            Spell::Heal => 12,
+        }
+    }
+}
+
+impl std::fmt::Display for Spell {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Spell::Lightning => write!(f, "lightning bolt"),
+            Spell::Heal => write!(f, "heal"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum PlayerClass {
+    #[default]
+    Warrior,
+    Mage,
+    Rogue,
+}
+
+impl PlayerClass {
+    pub const ALL: &'static [PlayerClass] = &[PlayerClass::Warrior, PlayerClass::Mage, PlayerClass::Rogue];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            PlayerClass::Warrior => "tough, starts with a sword",
+            PlayerClass::Mage => "frail, casts spells",
+            PlayerClass::Rogue => "quick to hit, starts with arrows",
+        }
+    }
+
+    // max hp, power and defense
+    pub fn stats(self) -> (i32, i32, i32) {
+        match self {
+            PlayerClass::Warrior => (120, 4, 2),
+            PlayerClass::Mage => (70, 3, 0),
+            PlayerClass::Rogue => (90, 5, 1),
+        }
+    }
+
+    // chance of spotting each hidden trap in range with one search
+    pub fn search_chance(self) -> f32 {
+        match self {
+            PlayerClass::Rogue => 0.8,
+            PlayerClass::Warrior | PlayerClass::Mage => 0.5,
+        }
+    }
+
+    pub fn disarm_chance(self) -> f32 {
+        match self {
+            PlayerClass::Rogue => 0.9,
+            PlayerClass::Warrior | PlayerClass::Mage => 0.6,
+        }
+    }
+
+    // swaps the stock stats for the class's and packs its kit next to the dagger, which a warrior
+    // trades for a sword
+    pub fn outfit(self, player: &mut Object, game: &mut Game) {
+        let (max_hp, power, defense) = self.stats();
+        if let Some(ref mut fighter) = player.fighter {
+            fighter.base_max_hp = max_hp;
+            fighter.hp = max_hp;
+            fighter.base_power = power;
+            fighter.base_defense = defense;
+        }
+
+        match self {
+            PlayerClass::Warrior => {
+                game.inventory.retain(|item| item.item != Some(Item::Sword));
+                game.inventory.push(starting_gear(Item::Sword, Slot::RightHand));
+            }
+            PlayerClass::Mage => {
+                if let Some(ref mut fighter) = player.fighter {
+                    fighter.max_mana = MAGE_MANA;
+                    fighter.mana = MAGE_MANA;
+                }
+                game.spells = vec![Spell::Lightning, Spell::Heal];
+                let mut lightning = create_item(Item::Lightning, 0, 0);
+                lightning.quantity = 3;
+                game.inventory.push(lightning);
+                let mut identify = create_item(Item::IdentifyScroll, 0, 0);
+                identify.quantity = 2;
+                game.inventory.push(identify);
+                game.identify(Item::Lightning);
+                game.identify(Item::IdentifyScroll);
+            }
+            PlayerClass::Rogue => {
+                let mut arrows = create_item(Item::Arrow, 0, 0);
+                arrows.quantity = 12;
+                game.inventory.push(arrows);
+                game.inventory.push(create_item(Item::Ration, 0, 0));
+            }
+        }
+        game.class = self;
+    }
+}
+
+impl std::fmt::Display for PlayerClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PlayerClass::Warrior => write!(f, "Warrior"),
+            PlayerClass::Mage => write!(f, "Mage"),
+            PlayerClass::Rogue => write!(f, "Rogue"),
+        }
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Difficulty::Easy => write!(f, "Easy"),
+            Difficulty::Normal => write!(f, "Normal"),
+            Difficulty::Hard => write!(f, "Hard"),
+        }
+    }
+}
+
+pub struct RoomsGenerator;
+
+impl MapGenerator for RoomsGenerator {
+    fn generate(&self, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, config: &Config) -> GeneratedMap {
+        let mut map = vec![vec![Tile::wall(); config.map_height as usize]; config.map_width as usize];
+        let mut rooms: Vec<Rect> = vec![];
+
+        for _ in 0..MAX_ROOMS {
+            let w = rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            let h = rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+
+            let x = rng().gen_range(0, config.map_width - w);
+            let y = rng().gen_range(0, config.map_height - h);
+
+            let new_room = Rect::new(x, y, w, h);
+
+            let failed = rooms
+                .iter()
+                .any(|other_room|new_room.intersect_with(other_room));
+
+            if !failed {
+                create_room(new_room, &mut map);
+                place_object(new_room, &map, objects, level, difficulty);
+                let (new_x, new_y) = new_room.center();
+                if !rooms.is_empty() {
+                    let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
+
+                    if rng().gen() {
+                        create_h_tunnel(prev_x, new_x, prev_y, &mut map);
+                        create_v_tunnel(prev_y, new_y, new_x, &mut map);
+                    } else {
+                        create_v_tunnel(prev_y, new_y, prev_x, &mut map);
+                        create_h_tunnel(prev_x, new_x, new_y, &mut map);
+                    }
+                }
+
+                rooms.push(new_room);
+            }
+        }
+
+        GeneratedMap {
+            map,
+            player_start: rooms[0].center(),
+            stairs: rooms[rooms.len() - 1].center(),
+            rooms,
+        }
+    }
+}
+
+// a leaf must hold the biggest room plus the wall it shares with its sibling
+pub const BSP_MIN_LEAF: i32 = ROOM_MAX_SIZE + 1;
+
+pub struct BspGenerator;
+
+impl MapGenerator for BspGenerator {
+    fn generate(&self, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, config: &Config) -> GeneratedMap {
+        let mut map = vec![vec![Tile::wall(); config.map_height as usize]; config.map_width as usize];
+        let mut rooms = vec![];
+
+        bsp_carve(Rect::new(0, 0, config.map_width, config.map_height), &mut map, &mut rooms);
+        for &room in &rooms {
+            place_object(room, &map, objects, level, difficulty);
+        }
+
+        GeneratedMap {
+            map,
+            player_start: rooms[0].center(),
+            stairs: rooms[rooms.len() - 1].center(),
+            rooms,
+        }
+    }
+}
+
+// splits the area until it's too small, carves a room in each leaf and returns
+// one room of the subtree so the caller can connect it to its sibling
+fn bsp_carve(area: Rect, map: &mut Map, rooms: &mut Vec<Rect>) -> Rect {
+    let width = area.x2 - area.x1;
+    let height = area.y2 - area.y1;
+    let can_split_x = width >= 2 * BSP_MIN_LEAF;
+    let can_split_y = height >= 2 * BSP_MIN_LEAF;
+
+    let split_x = match (can_split_x, can_split_y) {
+        (false, false) => {
+            let w = rng().gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, width - 1) + 1);
+            let h = rng().gen_range(ROOM_MIN_SIZE, cmp::min(ROOM_MAX_SIZE, height - 1) + 1);
+            let x = rng().gen_range(area.x1, area.x2 - w);
+            let y = rng().gen_range(area.y1, area.y2 - h);
+            let room = Rect::new(x, y, w, h);
+            create_room(room, map);
+            rooms.push(room);
+            return room;
+        }
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => width > height || (width == height && rng().gen()),
+    };
+
+    let (first, second) = if split_x {
+        let at = rng().gen_range(BSP_MIN_LEAF, width - BSP_MIN_LEAF + 1);
+        (
+            Rect::new(area.x1, area.y1, at, height),
+            Rect::new(area.x1 + at, area.y1, width - at, height),
+        )
+    } else {
+        let at = rng().gen_range(BSP_MIN_LEAF, height - BSP_MIN_LEAF + 1);
+        (
+            Rect::new(area.x1, area.y1, width, at),
+            Rect::new(area.x1, area.y1 + at, width, height - at),
+        )
+    };
+
+    let first_room = bsp_carve(first, map, rooms);
+    let second_room = bsp_carve(second, map, rooms);
+
+    let (x1, y1) = first_room.center();
+    let (x2, y2) = second_room.center();
+    if rng().gen() {
+        create_h_tunnel(x1, x2, y1, map);
+        create_v_tunnel(y1, y2, x2, map);
+    } else {
+        create_v_tunnel(y1, y2, x1, map);
+        create_h_tunnel(x1, x2, y2, map);
+    }
+
+    first_room
+}
+
+pub const CAVE_WALL_CHANCE: f32 = 0.45;
+pub const CAVE_SMOOTHING_STEPS: usize = 5;
+pub const CAVE_SPAWN_AREAS: usize = 12;
+pub const CAVE_THICKET_CHANCE: f32 = 0.05;
+pub const CAVE_POOL_CHANCE: f32 = 0.04;
+pub const CAVE_CHASMS: usize = 2;
+
+pub struct CaveGenerator;
+
+impl MapGenerator for CaveGenerator {
+    fn generate(&self, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, config: &Config) -> GeneratedMap {
+        let mut map = vec![vec![Tile::wall(); config.map_height as usize]; config.map_width as usize];
+        for x in 1..config.map_width - 1 {
+            for y in 1..config.map_height - 1 {
+                if rng().gen::<f32>() >= CAVE_WALL_CHANCE {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+
+        for _ in 0..CAVE_SMOOTHING_STEPS {
+            map = smooth_cave(&map);
+        }
+
+        let mut floor = largest_region(&mut map);
+        add_cave_features(&mut map, &mut floor);
+
+        // monsters and items are spread over a few room-sized areas around random floor tiles
+        for _ in 0..CAVE_SPAWN_AREAS {
+            let &(x, y) = floor.choose(&mut rng()).unwrap();
+            let area = Rect::new(x - ROOM_MIN_SIZE / 2, y - ROOM_MIN_SIZE / 2, ROOM_MIN_SIZE, ROOM_MIN_SIZE);
+            if area.x1 >= 0 && area.y1 >= 0 && area.x2 < config.map_width && area.y2 < config.map_height {
+                place_object(area, &map, objects, level, difficulty);
+            }
+        }
+
+        let free: Vec<(i32, i32)> = floor
+            .into_iter()
+            .filter(|&(x, y)| !objects.iter().skip(1).any(|o| o.blocks && o.occupies(x, y)))
+            .collect();
+        let player_start = *free.choose(&mut rng()).unwrap();
+        let stairs = *free
+            .iter()
+            .max_by_key(|&&(x, y)| (x - player_start.0).pow(2) + (y - player_start.1).pow(2))
+            .unwrap();
+
+        // a way down past the guardians would make them pointless
+        if level < FINAL_LEVEL && !is_boss_level(level) {
+            add_chasms(&mut map, &free, objects, &[player_start, stairs]);
+        }
+
+        GeneratedMap {
+            map,
+            player_start,
+            stairs,
+            rooms: vec![],
+        }
+    }
+}
+
+fn smooth_cave(map: &Map) -> Map {
+    let (width, height) = map_size(map);
+    let mut smoothed = map.clone();
+    for x in 1..width - 1 {
+        for y in 1..height - 1 {
+            let walls = (-1..=1)
+                .flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+                .filter(|&(nx, ny)| map[nx as usize][ny as usize].blocked)
+                .count();
+            smoothed[x as usize][y as usize] = if walls >= 5 {
+                Tile::wall()
+            } else {
+                Tile::empty()
+            };
+        }
+    }
+    smoothed
+}
+
+// scatters thickets over the floor and pools in the open, taking the pools out of the floor list;
+// a pool only goes where all eight neighbours are floor, so there's always a way round it
+fn add_cave_features(map: &mut Map, floor: &mut Vec<(i32, i32)>) {
+    for &(x, y) in floor.iter() {
+        let open = (-1..=1)
+            .flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+            .all(|(nx, ny)| !map[nx as usize][ny as usize].blocked);
+        let roll = rng().gen::<f32>();
+        if open && roll < CAVE_POOL_CHANCE {
+            map[x as usize][y as usize] = Tile::pool();
+        } else if roll < CAVE_POOL_CHANCE + CAVE_THICKET_CHANCE {
+            map[x as usize][y as usize] = Tile::thicket();
+        }
+    }
+    floor.retain(|&(x, y)| !map[x as usize][y as usize].blocked);
+}
+
+// opens a few small pits around random floor tiles, leaving the given spots and anything placed there alone
+fn add_chasms(map: &mut Map, floor: &[(i32, i32)], objects: &[Object], keep_clear: &[(i32, i32)]) {
+    for _ in 0..CAVE_CHASMS {
+        let &(x, y) = match floor.choose(&mut rng()) {
+            Some(tile) => tile,
+            None => return,
+        };
+        for (cx, cy) in (x - 1..=x + 1).flat_map(|cx| (y - 1..=y + 1).map(move |cy| (cx, cy))) {
+            if floor.contains(&(cx, cy))
+                && !keep_clear.contains(&(cx, cy))
+                && !objects.iter().any(|object| object.occupies(cx, cy))
+            {
+                map[cx as usize][cy as usize] = Tile::chasm();
+            }
+        }
+    }
+}
+
+// walls off every floor area except the biggest one, and returns its tiles
+fn largest_region(map: &mut Map) -> Vec<(i32, i32)> {
+    let (width, height) = map_size(map);
+    let mut seen = vec![vec![false; height as usize]; width as usize];
+    let mut largest: Vec<(i32, i32)> = vec![];
+
+    for x in 0..width {
+        for y in 0..height {
+            if seen[x as usize][y as usize] || map[x as usize][y as usize].blocked {
+                continue;
+            }
+
+            let mut region = vec![];
+            let mut stack = vec![(x, y)];
+            seen[x as usize][y as usize] = true;
+            while let Some((cx, cy)) = stack.pop() {
+                region.push((cx, cy));
+                for &(nx, ny) in &[(cx + 1, cy), (cx - 1, cy), (cx, cy + 1), (cx, cy - 1)] {
+                    if nx >= 0 && ny >= 0 && nx < width && ny < height
+                        && !seen[nx as usize][ny as usize]
+                        && !map[nx as usize][ny as usize].blocked
+                    {
+                        seen[nx as usize][ny as usize] = true;
+                        stack.push((nx, ny));
+                    }
+                }
+            }
+
+            if region.len() > largest.len() {
+                largest = region;
+            }
+        }
+    }
+
+    for x in 0..width {
+        for y in 0..height {
+            map[x as usize][y as usize] = Tile::wall();
+        }
+    }
+    for &(x, y) in &largest {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+    largest
+}
+
+// '#' wall, '.' floor, '!' item, 'm' monster, 'B' boss; every vault needs a way in
+pub const VAULTS: &[&str] = &[
+    "#######\n\
+     #.!.!.#\n\
+     #..B..#\n\
+     #.!.!.#\n\
+     ###.###",
+    "#####\n\
+     #m!m#\n\
+     #.!.#\n\
+     ##.##",
+    "##.##\n\
+     #.m.#\n\
+     .m!m.\n\
+     #.m.#\n\
+     ##.##",
+];
+
+// positions are relative to the prefab's top-left corner
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpawnMarker {
+    Item(i32, i32),
+    Monster(i32, i32),
+    Boss(i32, i32),
+}
+
+// tiles are indexed [x][y] like the map, short lines are padded with floor
+pub fn parse_prefab(prefab: &str) -> (Vec<Vec<Tile>>, Vec<SpawnMarker>) {
+    let lines: Vec<&str> = prefab.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    let mut tiles = vec![vec![Tile::empty(); lines.len()]; width];
+    let mut markers = vec![];
+
+    for (y, line) in lines.iter().enumerate() {
+        for (x, glyph) in line.chars().enumerate() {
+            let (px, py) = (x as i32, y as i32);
+            match glyph {
+                '#' => tiles[x][y] = Tile::wall(),
+                '!' => markers.push(SpawnMarker::Item(px, py)),
+                'm' => markers.push(SpawnMarker::Monster(px, py)),
+                'B' => markers.push(SpawnMarker::Boss(px, py)),
+                _ => {}
+            }
+        }
+    }
+    (tiles, markers)
+}
+
+// stamps a random vault in the middle of a room big enough to keep a ring of floor around it,
+// leaving the rooms holding the start and the stairs alone
+fn place_vault(map: &mut Map, objects: &mut Vec<Object>, rooms: &[Rect], avoid: &[(i32, i32)], level: u32, difficulty: Difficulty) {
+    let prefab = match VAULTS.choose(&mut rng()) {
+        Some(prefab) => prefab,
+        None => return,
+    };
+    let (tiles, markers) = parse_prefab(prefab);
+    let width = tiles.len() as i32;
+    let height = tiles.first().map_or(0, |column| column.len()) as i32;
+
+    let room = rooms.iter().find(|room| {
+        room.x2 - room.x1 > width + 2
+            && room.y2 - room.y1 > height + 2
+            && !avoid.iter().any(|&(x, y)| room.covers(x, y))
+    });
+    let room = match room {
+        Some(room) => room,
+        None => return,
+    };
+
+    let origin_x = room.x1 + 1 + (room.x2 - room.x1 - 1 - width) / 2;
+    let origin_y = room.y1 + 1 + (room.y2 - room.y1 - 1 - height) / 2;
+    let inside = |x: i32, y: i32| {
+        x >= origin_x && x < origin_x + width && y >= origin_y && y < origin_y + height
+    };
+
+    // whatever the room was stocked with gives way to the vault's own contents
+    let mut index = 0;
+    objects.retain(|object| {
+        index += 1;
+        index - 1 == PLAYER || !inside(object.x, object.y)
+    });
+
+    for (x, column) in tiles.into_iter().enumerate() {
+        for (y, tile) in column.into_iter().enumerate() {
+            map[(origin_x + x as i32) as usize][(origin_y + y as i32) as usize] = tile;
+        }
+    }
+
+    for marker in markers {
+        match marker {
+            SpawnMarker::Item(x, y) => {
+                objects.push(create_item(random_item(level), origin_x + x, origin_y + y));
+            }
+            SpawnMarker::Monster(x, y) => {
+                let mut monster = create_monster(&random_monster(level), origin_x + x, origin_y + y, level);
+                difficulty.adjust_monster(&mut monster);
+                if let Some(Ai::Basic { .. }) = monster.ai {
+                    monster.ai = Some(Ai::Sleeping);
+                }
+                objects.push(monster);
+            }
+            SpawnMarker::Boss(x, y) => {
+                let mut boss = create_monster("boss", origin_x + x, origin_y + y, level);
+                difficulty.adjust_monster(&mut boss);
+                objects.push(boss);
+            }
+        }
+    }
+}
+
+// returns the map along with the rooms that are lit
+pub fn make_map(objects: &mut Vec<Object>, level: u32, generator: GeneratorKind, difficulty: Difficulty, config: &Config) -> (Map, Vec<Rect>) {
+
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+    objects.truncate(1);
+
+    let mut generated = generator.generator().generate(objects, level, difficulty, config);
+
+    if level >= VAULT_MIN_LEVEL && rng().gen::<f32>() < VAULT_CHANCE {
+        let avoid = [generated.player_start, generated.stairs];
+        place_vault(&mut generated.map, objects, &generated.rooms, &avoid, level, difficulty);
+    }
+
+    // nothing generated may stand where the player arrives or on the stairs, which need open floor
+    let player_start = nearest_free_tile(generated.player_start, 1, &generated.map, objects, &[]);
+    let stairs = nearest_free_tile(generated.stairs, 1, &generated.map, objects, &[player_start]);
+    objects[PLAYER].set_pos(player_start.0, player_start.1);
+
+    let (stairs_x, stairs_y) = stairs;
+    if level >= FINAL_LEVEL {
+        // the bottom of the dungeon, the lich waits where the stairs would be
+        let (x, y) = nearest_free_tile(stairs, BOSS_SIZE, &generated.map, objects, &[player_start]);
+        let mut lich = create_monster("lich", x, y, level);
+        difficulty.adjust_monster(&mut lich);
+        objects.push(lich);
+    } else {
+        if is_boss_level(level) {
+            // the guardian waits beside the stairs it keeps shut
+            let (x, y) = nearest_free_tile(stairs, BOSS_SIZE, &generated.map, objects, &[player_start, stairs]);
+            let mut guardian = create_monster("boss", x, y, level);
+            difficulty.adjust_monster(&mut guardian);
+            guardian.guardian = true;
+            guardian.size = BOSS_SIZE;
+            objects.push(guardian);
+        }
+        let mut stairs = Object::new(
+            stairs_x,
+            stairs_y,
+            '<',
+            "Stairs",
+            colors::WHITE,
+            false,
+        );
+        stairs.always_visible = true;
+        objects.push(stairs);
+    }
+
+    if level >= TRAP_MIN_LEVEL {
+        place_traps(&mut generated.map, objects, player_start, level);
+    }
+
+    let lit_rooms = generated
+        .rooms
+        .into_iter()
+        .filter(|_| rng().gen::<f32>() < LIT_ROOM_CHANCE)
+        .collect();
+
+    (generated.map, lit_rooms)
+}
+
+// hidden traps on open floor, never where something already stands or the player arrives
+fn place_traps(map: &mut Map, objects: &[Object], player_start: (i32, i32), level: u32) {
+    let (map_width, map_height) = map_size(map);
+    let mut floor: Vec<(i32, i32)> = (0..map_width)
+        .flat_map(|x| (0..map_height).map(move |y| (x, y)))
+        .filter(|&(x, y)| !map[x as usize][y as usize].blocked && !map[x as usize][y as usize].chasm)
+        .filter(|&pos| pos != player_start && !objects.iter().any(|object| object.occupies(pos.0, pos.1)))
+        .collect();
+
+    let count = rng().gen_range(0, cmp::min(level as i32, MAX_TRAPS) + 1);
+    for _ in 0..count {
+        if floor.is_empty() {
+            break;
+        }
+        let (x, y) = floor.swap_remove(rng().gen_range(0, floor.len()));
+        let kind = TrapKind::ALL[rng().gen_range(0, TrapKind::ALL.len())];
+        map[x as usize][y as usize].trap = Some(Trap { kind, found: false });
+    }
+}
+
+// sets off the trap under the given spot on whoever is standing there
+pub fn spring_trap(x: i32, y: i32, victim_id: usize, objects: &mut [Object], game: &mut Game) {
+    let trap = match game.map[x as usize][y as usize].trap.as_mut() {
+        Some(trap) => trap,
+        None => return,
+    };
+    trap.found = true;
+    let kind = trap.kind;
+    let (damage, damage_type) = kind.damage();
+    game.log.add_categorized(
+        format!("The {} springs a {} for {} hit points!", objects[victim_id].name, kind, objects[victim_id].resisted_damage(damage, damage_type)),
+        MessageCategory::Danger,
+    );
+    game.last_attacker = Some(format!("a {}", kind));
+    objects[victim_id].take_damage(damage, damage_type, None, game);
+}
+
+// looks around for hidden traps, returning how many turned up
+pub fn search_for_traps(objects: &[Object], game: &mut Game) -> usize {
+    let (map_width, map_height) = map_size(&game.map);
+    let mut found = 0;
+    for x in 0..map_width {
+        for y in 0..map_height {
+            if objects[PLAYER].distance(x, y) > SEARCH_RADIUS {
+                continue;
+            }
+            if let Some(ref mut trap) = game.map[x as usize][y as usize].trap {
+                if !trap.found && rng().gen::<f32>() < game.class.search_chance() {
+                    trap.found = true;
+                    found += 1;
+                    game.log.add(format!("You find a {}.", trap.kind), colors::LIGHT_YELLOW);
+                }
+            }
+        }
+    }
+    if found == 0 {
+        game.log.add("You search around but find nothing.", colors::LIGHT_GREY);
+    }
+    found
+}
+
+// works on a known trap next to the player, a fumble sets it off on them; false if there was none
+pub fn disarm_trap(objects: &mut [Object], game: &mut Game) -> bool {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let (map_width, map_height) = map_size(&game.map);
+    let known = (player_x - 1..=player_x + 1)
+        .flat_map(|x| (player_y - 1..=player_y + 1).map(move |y| (x, y)))
+        .filter(|&(x, y)| x >= 0 && y >= 0 && x < map_width && y < map_height)
+        .find(|&(x, y)| game.map[x as usize][y as usize].trap.is_some_and(|trap| trap.found));
+    let (x, y) = match known {
+        Some(pos) => pos,
+        None => {
+            game.log.add("There is no known trap next to you.", colors::RED);
+            return false;
+        }
+    };
+
+    if rng().gen::<f32>() < game.class.disarm_chance() {
+        if let Some(trap) = game.map[x as usize][y as usize].trap.take() {
+            game.log.add(format!("You disarm the {}.", trap.kind), colors::LIGHT_GREEN);
+        }
+    } else {
+        game.log.add("Your hand slips!", colors::RED);
+        spring_trap(x, y, PLAYER, objects, game);
+    }
+    true
+}
+
+// a lit room is seen in full as soon as the player steps inside
+pub fn lit_room_around(player: &Object, game: &Game) -> Option<Rect> {
+    game.lit_rooms.iter().find(|room| room.contains(player.x, player.y)).cloned()
+}
+
+pub fn is_tile_visible(x: i32, y: i32, fov: &impl Visibility, lit_room: Option<Rect>) -> bool {
+    fov.is_in_fov(x, y) || lit_room.is_some_and(|room| room.covers(x, y))
+}
+
+pub fn remember_monsters(objects: &mut [Object], game: &Game, fov: &impl Visibility) {
+    let lit_room = lit_room_around(&objects[PLAYER], game);
+    for object in objects.iter_mut() {
+        if object.fighter.is_some() && object.ai.is_some() && is_tile_visible(object.x, object.y, fov, lit_room) {
+            object.last_seen = Some(object.pos());
+        } else if let Some((x, y)) = object.last_seen {
+            // the remembered spot is back in view and whatever stood there is gone
+            if is_tile_visible(x, y, fov, lit_room) {
+                object.last_seen = None;
+            }
+        }
+    }
+}
+
+pub fn player_move_or_attack(dx: i32, dy: i32, auto_pickup: bool, objects: &mut Vec<Object>, game: &mut Game){
+    let x = objects[PLAYER].x + dx;
+    let y = objects[PLAYER].y + dy;
+
+    let target_id = objects.iter().position(|object |object.fighter.is_some() && object.occupies(x, y));
+
+    match target_id {
+        Some(target_id) if objects[target_id].is_hostile() => {
+            let (player, target) = mut_two(PLAYER, target_id, objects);
+            player.attack(target, game);
+        }
+        Some(target_id) => {
+            // step past friendly creatures by trading places
+            let (player_x, player_y) = objects[PLAYER].pos();
+            objects[PLAYER].set_pos(x, y);
+            objects[target_id].set_pos(player_x, player_y);
+            game.log.add_categorized(format!("You swap places with the {}.", objects[target_id].name), MessageCategory::System);
+            player_stepped(auto_pickup, objects, game);
+        }
+        None => {
+            move_by(PLAYER, dx, dy, &game.map, objects);
+            if objects[PLAYER].pos() == (x, y) {
+                player_stepped(auto_pickup, objects, game);
+            }
+        }
+    }
+}
+
+// whatever waits on the tile the player just stepped onto, however they got there
+fn player_stepped(auto_pickup: bool, objects: &mut Vec<Object>, game: &mut Game) {
+    let (x, y) = objects[PLAYER].pos();
+    game.player_moved = true;
+    spring_trap(x, y, PLAYER, objects, game);
+    if auto_pickup {
+        pick_up_ammo(objects, game);
+    }
+}
+
+pub fn stairs_position(objects: &[Object]) -> Option<(i32, i32)> {
+    objects.iter().find(|object| object.name == "Stairs").map(Object::pos)
+}
+
+pub fn is_boss_level(level: u32) -> bool {
+    level.is_multiple_of(BOSS_LEVEL_INTERVAL)
+}
+
+pub fn stairs_sealed(objects: &[Object]) -> bool {
+    objects.iter().any(|object| object.guardian && object.alive)
+}
+
+// the compass point of the stairs from the player, once they've been found
+pub fn stairs_direction(objects: &[Object], game: &Game) -> Option<&'static str> {
+    let (x, y) = stairs_position(objects)?;
+    if !game.map[x as usize][y as usize].explored {
+        return None;
+    }
+    let dx = x - objects[PLAYER].x;
+    let dy = y - objects[PLAYER].y;
+    if (dx, dy) == (0, 0) {
+        return Some("here");
+    }
+    // rounded the same way move_towards picks its step
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    Some(match (dx, dy) {
+        (0, -1) => "N",
+        (1, -1) => "NE",
+        (1, 0) => "E",
+        (1, 1) => "SE",
+        (0, 1) => "S",
+        (-1, 1) => "SW",
+        (-1, 0) => "W",
+        _ => "NW",
+    })
+}
+
+// the closest spot where something of the given size fits, clear of chasms and of the tiles to keep clear;
+// the player doesn't count, being either the one placed or somewhere the caller keeps clear
+pub fn nearest_free_tile(target: (i32, i32), size: i32, map: &Map, objects: &[Object], keep_clear: &[(i32, i32)]) -> (i32, i32) {
+    let (map_width, map_height) = map_size(map);
+    let covers = |x: i32, y: i32, (cell_x, cell_y): (i32, i32)| cell_x >= x && cell_y >= y && cell_x < x + size && cell_y < y + size;
+    let free = |&(x, y): &(i32, i32)| {
+        footprint_fits(x, y, size, map, objects, Some(PLAYER))
+            && (x..x + size).all(|cell_x| (y..y + size).all(|cell_y| !map[cell_x as usize][cell_y as usize].chasm))
+            && !keep_clear.iter().any(|&tile| covers(x, y, tile))
+    };
+    // usually the target itself will do, which spares a look over the whole map
+    if free(&target) {
+        return target;
+    }
+    (0..map_width)
+        .flat_map(|x| (0..map_height).map(move |y| (x, y)))
+        .filter(free)
+        .min_by_key(|&(x, y)| (x - target.0).pow(2) + (y - target.1).pow(2))
+        .unwrap_or(target)
+}
+
+// the whole level as text, explored or not: '#' walls, '.' floor, '"' thickets, '~' pools,
+// ' ' chasms, '^' traps, with every object's glyph on top the way render_all stacks them
+pub fn map_to_ascii(game: &Game, objects: &[Object]) -> String {
+    let (map_width, map_height) = map_size(&game.map);
+    let mut rows: Vec<Vec<char>> = (0..map_height)
+        .map(|y| {
+            (0..map_width)
+                .map(|x| {
+                    let tile = &game.map[x as usize][y as usize];
+                    match (tile.blocked, tile.block_sight) {
+                        _ if tile.chasm => ' ',
+                        _ if tile.trap.is_some() => '^',
+                        (true, true) => '#',
+                        (false, true) => '"',
+                        (true, false) => '~',
+                        (false, false) => '.',
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut stacked: Vec<&Object> = objects.iter().collect();
+    stacked.sort_by_key(|o| o.draw_layer());
+    for object in stacked {
+        for (x, y) in object.cells() {
+            if x >= 0 && y >= 0 && x < map_width && y < map_height {
+                rows[y as usize][x as usize] = object.char;
+            }
+        }
+    }
+
+    rows.into_iter().map(|row| row.into_iter().collect::<String>() + "\n").collect()
+}
+
+pub fn dump_map(game: &Game, objects: &[Object], path: &str) -> Result<(), Box<dyn Error>> {
+    let mut file = File::create(path)?;
+    file.write_all(map_to_ascii(game, objects).as_bytes())?;
+    Ok(())
+}
+
+pub fn descend(objects: &mut Vec<Object>, game: &mut Game, config: &Config) {
+    if game.dungeon_level >= FINAL_LEVEL {
+        return;
+    }
+    game.log.add_categorized("You take a moment to rest.", MessageCategory::Level);
+    let heal_hp = objects[PLAYER].max_hp(game) / 2;
+    objects[PLAYER].cast("heal", heal_hp, game);
+
+    game.log.add_categorized(
+        "After a rare moment of peace, you going further in the dungeon.. As always",
+        MessageCategory::Level,
+    );
+    enter_next_level(objects, game, config);
+}
+
+// through a chasm: no rest on the way and a hard landing somewhere on the level below
+pub fn fall_into_chasm(objects: &mut Vec<Object>, game: &mut Game, config: &Config) {
+    game.log.add_categorized("The ground gives way and you fall into the darkness!", MessageCategory::Danger);
+    enter_next_level(objects, game, config);
+
+    let (map_width, map_height) = map_size(&game.map);
+    let landings: Vec<(i32, i32)> = (0..map_width)
+        .flat_map(|x| (0..map_height).map(move |y| (x, y)))
+        .filter(|&(x, y)| !game.map[x as usize][y as usize].chasm && footprint_fits(x, y, 1, &game.map, objects, Some(PLAYER)))
+        .collect();
+    if let Some(&(x, y)) = landings.choose(&mut rng()) {
+        objects[PLAYER].set_pos(x, y);
+        game.entrance = Some((x, y));
+    }
+
+    game.log.add_categorized(
+        format!("You land hard, taking {} damage.", objects[PLAYER].resisted_damage(FALL_DAMAGE, DamageType::Physical)),
+        MessageCategory::Danger,
+    );
+    game.last_attacker = Some("a fall".into());
+    objects[PLAYER].take_damage(FALL_DAMAGE, DamageType::Physical, None, game);
+}
+
+fn enter_next_level(objects: &mut Vec<Object>, game: &mut Game, config: &Config) {
+    game.dungeon_level += 1;
+    // they'd float over the same spots on the new level
+    game.damage_labels.clear();
+    game.generator = GeneratorKind::for_level(game.dungeon_level);
+    let theme = ThemeKind::for_level(game.dungeon_level);
+    if theme != game.theme {
+        game.log.add_categorized(format!("The walls around you change, you have entered the {}.", theme.theme().name), MessageCategory::Level);
+        game.theme = theme;
+    }
+    let (map, lit_rooms) = make_map(objects, game.dungeon_level, game.generator, game.difficulty, config);
+    game.map = map;
+    game.lit_rooms = lit_rooms;
+    game.entrance = Some(objects[PLAYER].pos());
+    land_fallen_monsters(objects, game);
+    if game.dungeon_level == FINAL_LEVEL {
+        game.log.add_categorized("The stairs end here. Something ancient waits on this floor.", MessageCategory::Danger);
+    } else if is_boss_level(game.dungeon_level) {
+        game.log.add_categorized("A dreadful presence guards the stairs of this level.", MessageCategory::Danger);
+    }
+}
+
+// whatever fell from the level above comes down anywhere there's room for it
+fn land_fallen_monsters(objects: &mut Vec<Object>, game: &mut Game) {
+    let (map_width, map_height) = map_size(&game.map);
+    for mut monster in std::mem::take(&mut game.fallen) {
+        let landings: Vec<(i32, i32)> = (0..map_width)
+            .flat_map(|x| (0..map_height).map(move |y| (x, y)))
+            .filter(|&(x, y)| !game.map[x as usize][y as usize].chasm && footprint_fits(x, y, monster.size, &game.map, objects, None))
+            .collect();
+        if let Some(&(x, y)) = landings.choose(&mut rng()) {
+            monster.set_pos(x, y);
+            monster.previous_pos = None;
+            objects.push(monster);
+        }
+    }
+}
+
+// explorer mode: back on your feet at the level entrance, a good deal less experienced
+pub fn respawn_player(objects: &mut [Object], game: &mut Game) {
+    let entrance = game.entrance.unwrap_or_else(|| objects[PLAYER].pos());
+    let spot = nearest_free_tile(entrance, 1, &game.map, objects, &[]);
+
+    let max_hp = objects[PLAYER].max_hp(game);
+    let player = &mut objects[PLAYER];
+    player.alive = true;
+    player.char = '@';
+    player.color = colors::WHITE;
+    player.set_pos(spot.0, spot.1);
+    player.effects.clear();
+    if let Some(ref mut fighter) = player.fighter {
+        fighter.hp = max_hp;
+        fighter.xp = fighter.xp * (100 - EXPLORER_XP_PENALTY) / 100;
+    }
+
+    game.nutrition = cmp::max(game.nutrition, HUNGRY_NUTRITION + 1);
+    game.last_attacker = None;
+    game.log.add(
+        "You come to at the entrance of the level, your hard-won experience fading.",
+        colors::LIGHT_VIOLET,
+    );
+}
+
+// top-left map position of a view centred on focus, kept from running off the map's edges
+pub fn camera_origin(focus: (i32, i32), map_size: (i32, i32), view_size: (i32, i32)) -> (i32, i32) {
+    let axis = |focus: i32, map: i32, view: i32| cmp::max(0, cmp::min(focus - view / 2, map - view));
+    (
+        axis(focus.0, map_size.0, view_size.0),
+        axis(focus.1, map_size.1, view_size.1),
+    )
+}
+
+pub fn fill_fov(map: &Map, fov: &mut FovMap) {
+    let (map_width, map_height) = map_size(map);
+    for y in 0..map_height {
+        for x in 0..map_width {
+            fov.set(
+                x,
+                y,
+                !map[x as usize][y as usize].block_sight,
+                !map[x as usize][y as usize].blocked,
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SaveRef<'a> {
+    version: u32,
+    objects: &'a [Object],
+    game: &'a Game,
+}
+
+#[derive(Deserialize)]
+struct SaveData {
+    objects: Vec<Object>,
+    game: Game,
+}
+
+// the file is SAVE_MAGIC, a checksum of the rest, then the gzipped json
+pub fn save_game(objects: &[Object], game: &Game, slot: &str) -> Result<(), Box<dyn Error>> {
+    let save_data = serde_json::to_string(&SaveRef { version: SAVE_VERSION, objects, game })?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(save_data.as_bytes())?;
+    let compressed = encoder.finish()?;
+
+    let mut bytes = SAVE_MAGIC.to_vec();
+    bytes.extend_from_slice(&checksum(&compressed).to_le_bytes());
+    bytes.extend_from_slice(&compressed);
+    write_atomically(slot, &bytes)
+}
+
+// writes next to the target and renames over it, so a failed write never
+// leaves a half-written file where the old one was
+fn write_atomically(path: &str, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let temp_path = format!("{}.tmp", path);
+    let written = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&temp_path, path)) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(Box::new(e));
+    }
+    Ok(())
+}
+
+pub fn load_game(slot: &str) -> Result<(Vec<Object>, Game), Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    let mut file = File::open(slot)?;
+    file.read_to_end(&mut bytes)?;
+
+    let json_save_state = if bytes.starts_with(SAVE_MAGIC) {
+        decompress_save(&bytes[SAVE_MAGIC.len()..])?
+    } else {
+        // saves written before compression are plain json
+        String::from_utf8(bytes)?
+    };
+    let save = migrate_save(serde_json::from_str(&json_save_state)?)?;
+    let mut save = serde_json::from_value::<SaveData>(save)?;
+    save.game.log.turn = save.game.turns;
+    Ok((save.objects, save.game))
+}
+
+fn decompress_save(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    if bytes.len() < 4 {
+        return Err("save file corrupted".into());
+    }
+    let (stored, compressed) = bytes.split_at(4);
+    if u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]) != checksum(compressed) {
+        return Err("save file corrupted".into());
+    }
+
+    let mut json_save_state = String::new();
+    GzDecoder::new(compressed).read_to_string(&mut json_save_state)?;
+    Ok(json_save_state)
+}
+
+// 32-bit FNV-1a, enough to notice a truncated or damaged file
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193))
+}
+
+// saves from before versioning are a bare (objects, game) pair
+fn save_version(save: &Value) -> Result<u32, Box<dyn Error>> {
+    if save.is_array() {
+        return Ok(0);
+    }
+    match save["version"].as_u64() {
+        Some(version) => Ok(version as u32),
+        None => Err("the save has no version".into()),
+    }
+}
+
+// brings an older save up to the current layout one version at a time; fields that
+// are simply new get their #[serde(default)] when the result is deserialized
+fn migrate_save(mut save: Value) -> Result<Value, Box<dyn Error>> {
+    let version = save_version(&save)?;
+    if version > SAVE_VERSION {
+        return Err(format!("the save is from a newer version ({})", version).into());
+    }
+
+    for from in version..SAVE_VERSION {
+        save = match from {
+            0 => migrate_from_pair(save),
+            _ => unreachable!(),
+        };
+    }
+    Ok(save)
+}
+
+// version 1 wrapped the pair in a versioned object and gave basic AI a memory of the player
+fn migrate_from_pair(save: Value) -> Value {
+    let mut pair = match save {
+        Value::Array(pair) => pair,
+        other => return other,
+    };
+    let mut game = pair.pop().unwrap_or(Value::Null);
+    let mut objects = pair.pop().unwrap_or(Value::Null);
+
+    add_basic_ai_memory(&mut objects);
+    for field in &["inventory", "pending_objects"] {
+        if let Some(held) = game.get_mut(field) {
+            add_basic_ai_memory(held);
+        }
+    }
+
+    json!({ "version": 1, "objects": objects, "game": game })
+}
+
+fn add_basic_ai_memory(objects: &mut Value) {
+    for object in objects.as_array_mut().into_iter().flatten() {
+        if object["ai"] == "Basic" {
+            object["ai"] = json!({ "Basic": { "last_known": null, "turns": 0 } });
+        }
+    }
+}
+
+// a missing save counts as already deleted
+pub fn delete_save(slot: &str) -> Result<(), Box<dyn Error>> {
+    if let Err(e) = std::fs::remove_file(slot) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(Box::new(e));
+        }
+    }
+    Ok(())
+}
+
+pub fn load_scores() -> Result<Vec<ScoreEntry>, Box<dyn Error>> {
+    let mut json_scores = String::new();
+    let mut file = File::open(SCORES_FILE)?;
+    file.read_to_string(&mut json_scores)?;
+    let scores = serde_json::from_str::<Vec<ScoreEntry>>(&json_scores)?;
+    Ok(scores)
+}
+
+pub fn record_score(entry: ScoreEntry) -> Result<(), Box<dyn Error>> {
+    let mut scores = load_scores().unwrap_or_default();
+    scores.push(entry);
+    let scores_data = serde_json::to_string(&scores)?;
+    let mut file = File::create(SCORES_FILE)?;
+    file.write_all(scores_data.as_bytes())?;
+    Ok(())
+}
+
+pub fn new_world(config: &Config, difficulty: Difficulty) -> (Vec<Object>, Game) {
+    let mut player: Object = Object::new(0,0,'@', "player", colors::WHITE, true);
+    player.fighter = Some(Fighter {
+        base_max_hp: 100,
+        hp: 100,
+        base_defense: 1,
+        base_power: 4,
+        on_death: DeathCallback::Player,
+        damage_type: DamageType::Physical,
+        resistances: Resistances::default(),
+        mana: 0,
+        max_mana: 0,
+        speed: NORMAL_SPEED,
+        energy: 0,
+        perception: PERCEPTION_RADIUS,
+        reckless: false,
+        xp:0
+    });
+    player.alive= true;
+
+    let mut objects = vec![player];
+
+    let (map, lit_rooms) = make_map(&mut objects, 1, GeneratorKind::for_level(1), difficulty, config);
+
+    let mut game = Game {
+        map,
+        log: Messages::default(),
+        inventory: vec![],
+        dungeon_level: 1,
+        palette: PaletteKind::default(),
+        log_turns: false,
+        animate_moves: false,
+        damage_numbers: false,
+        stairs_compass: false,
+        damage_labels: vec![],
+        appearances: random_appearances(),
+        identified: vec![],
+        pending_objects: vec![],
+        fallen: vec![],
+        turns: 0,
+        last_attacker: None,
+        nutrition: MAX_NUTRITION,
+        generator: GeneratorKind::for_level(1),
+        theme: ThemeKind::for_level(1),
+        lit_rooms,
+        difficulty,
+        mode: GameMode::default(),
+        entrance: Some(objects[PLAYER].pos()),
+        won: false,
+        class: PlayerClass::default(),
+        spells: vec![],
+        sneaking: false,
+        player_moved: false,
+    };
+
+    // a short sword for the off hand, half the weight of a full one
+    let mut dagger = starting_gear(Item::Sword, Slot::LeftHand);
+    dagger.name = "dagger".into();
+    dagger.char = '-';
+    dagger.weight /= 2;
+    game.inventory.push(dagger);
+
+    if difficulty.starting_potions() > 0 {
+        let mut potions = create_item(Item::Heal, 0, 0);
+        potions.quantity = difficulty.starting_potions();
+        game.inventory.push(potions);
+        game.identify(Item::Heal);
+    }
+
+    (objects, game)
+}
+
+// everything that happens between two player moves
+pub fn end_player_turn(objects: &mut Vec<Object>, game: &mut Game, fov_map: &impl Visibility) {
+    game.turns += 1;
+    game.log.turn = game.turns;
+    turn_events(objects, game, fov_map);
+    tick_effects(objects, game);
+
+    if objects[PLAYER].alive {
+        for object in objects.iter_mut() {
+            object.previous_pos = Some(object.pos());
+        }
+        // a quick player lets less time pass per action
+        let elapsed = ACTION_COST * NORMAL_SPEED / player_speed(objects, game);
+        for id in 0..objects.len() {
+            take_monster_turns(id, elapsed, objects, game, fov_map);
+        }
+        game.player_moved = false;
+        drop_into_chasms(objects, game, fov_map);
+        objects.append(&mut game.pending_objects);
+        objects.retain(|o| o.ai != Some(Ai::Allied { lifetime: 0 }));
+    }
+}
+
+// monsters that blundered onto a chasm leave this level and wait to turn up on the next one
+fn drop_into_chasms(objects: &mut Vec<Object>, game: &mut Game, fov_map: &impl Visibility) {
+    let map = &game.map;
+    let falls = |object: &Object| object.ai.is_some() && object.alive && object.over_chasm(map);
+    let (fallen, staying): (Vec<Object>, Vec<Object>) = objects.drain(..).partition(falls);
+    *objects = staying;
+    for monster in fallen {
+        if fov_map.is_in_fov(monster.x, monster.y) {
+            game.log.add_categorized(
+                format!("The {} falls into the chasm, down to the level below!", monster.name),
+                MessageCategory::Combat,
+            );
+        }
+        game.fallen.push(monster);
+    }
+}
+
+// gives the monster its share of the elapsed time, then lets it act for as long as the energy lasts
+fn take_monster_turns(id: usize, elapsed: i32, objects: &mut [Object], game: &mut Game, fov_map: &impl Visibility) {
+    if objects[id].ai.is_none() {
+        return;
+    }
+    let speed = objects[id].speed();
+    match objects[id].fighter.as_mut() {
+        Some(fighter) => fighter.energy += speed * elapsed / NORMAL_SPEED,
+        // nothing to go by without a fighter, so it keeps to one action a turn
+        None => {
+            ai_take_turn(id, game, objects, fov_map);
+            return;
+        }
+    }
+
+    while objects[id].ai.is_some() && objects[PLAYER].alive && objects[id].fighter.is_some_and(|f| f.energy >= ACTION_COST) {
+        ai_take_turn(id, game, objects, fov_map);
+        if let Some(ref mut fighter) = objects[id].fighter {
+            fighter.energy -= ACTION_COST;
+        }
+    }
+}
+
+pub fn tick_effects(objects: &mut [Object], game: &mut Game) {
+    for (id, object) in objects.iter_mut().enumerate() {
+        for effect in object.effects.iter_mut() {
+            effect.turns -= 1;
+            if effect.turns <= 0 && id == PLAYER {
+                game.log.add(format!("Your {} wears off.", effect.kind.to_string().to_lowercase()), colors::LIGHT_GREY);
+            }
+        }
+        object.effects.retain(|e| e.turns > 0);
+    }
+}
+
+// periodic events driven by the turn counter
+pub fn turn_events(objects: &mut Vec<Object>, game: &mut Game, fov_map: &impl Visibility) {
+    game.nutrition -= 1;
+    if game.nutrition == HUNGRY_NUTRITION {
+        game.log.add_categorized("You are getting hungry.", MessageCategory::Danger);
+    } else if game.nutrition <= 0 {
+        game.nutrition = 0;
+        game.log.add_categorized("You are starving!", MessageCategory::Danger);
+        game.last_attacker = Some("starvation".to_string());
+        objects[PLAYER].take_damage(STARVATION_DAMAGE, DamageType::Physical, None, game);
+    }
+
+    if game.turns.is_multiple_of(MANA_REGEN_INTERVAL) {
+        if let Some(ref mut fighter) = objects[PLAYER].fighter {
+            fighter.mana = cmp::min(fighter.mana + 1, fighter.max_mana);
+        }
+    }
+
+    let wandering_due = game.difficulty.wandering_monster_interval().is_some_and(|interval| game.turns.is_multiple_of(interval));
+    if wandering_due {
+        let level_cleared = !objects.iter().any(|o| o.is_hostile());
+        if !level_cleared {
+            spawn_wandering_monster(objects, game, fov_map);
+        }
+    }
+}
+
+pub fn spawn_wandering_monster(objects: &mut Vec<Object>, game: &mut Game, fov_map: &impl Visibility) {
+    // a handful of tries to find a free floor tile the player can't see
+    for _ in 0..20 {
+        let (map_width, map_height) = map_size(&game.map);
+        let x = rng().gen_range(0, map_width);
+        let y = rng().gen_range(0, map_height);
+        if !is_blocked(x, y, &game.map, objects) && !game.map[x as usize][y as usize].chasm && !fov_map.is_in_fov(x, y) {
+            let mut monster = create_monster(&random_monster(game.dungeon_level), x, y, game.dungeon_level);
+            game.difficulty.adjust_monster(&mut monster);
+            objects.push(monster);
+            game.log.add_categorized("You hear something stirring in the distance...", MessageCategory::Danger);
+            return;
+        }
+    }
+}
+
+pub struct SimulationReport {
+    pub seed: u64,
+    pub turns: u64,
+    pub dungeon_level: u32,
+    pub player_level: i32,
+    pub outcome: String,
+}
+
+impl std::fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "seed {}: {} on dungeon level {} at level {} after {} turns",
+            self.seed, self.outcome, self.dungeon_level, self.player_level, self.turns
+        )
+    }
+}
+
+// plays a game without a window: the auto-player heads for the stairs and fights whatever is in reach
+pub fn run_simulation(seed: u64, turns: u64) -> SimulationReport {
+    seed_rng(seed);
+    let config = Config::default();
+    let (mut objects, mut game) = new_world(&config, Difficulty::default());
+    let mut fov = simulation_fov(&objects, &game);
+
+    while game.turns < turns && objects[PLAYER].alive && !game.won {
+        while can_level_up(&objects[PLAYER]) {
+            // alternate between constitution and strength
+            let stat = (objects[PLAYER].level % 2) as usize;
+            gain_level(&mut objects[PLAYER], stat, &mut game);
+        }
+
+        let (player_x, player_y) = objects[PLAYER].pos();
+        if stairs_position(&objects) == Some((player_x, player_y)) && !stairs_sealed(&objects) {
+            descend(&mut objects, &mut game, &config);
+            fov = simulation_fov(&objects, &game);
+        } else {
+            let enemy = objects
+                .iter()
+                .find(|o| o.is_hostile() && (o.x - player_x).abs() + (o.y - player_y).abs() == 1)
+                .map(Object::pos);
+            let step = match enemy {
+                Some((x, y)) => Some((x - player_x, y - player_y)),
+                None => stairs_position(&objects).and_then(|stairs| first_step(&game.map, (player_x, player_y), stairs)),
+            };
+            if let Some((dx, dy)) = step {
+                player_move_or_attack(dx, dy, false, &mut objects, &mut game);
+            }
+            objects.append(&mut game.pending_objects);
+            if objects[PLAYER].over_chasm(&game.map) {
+                fall_into_chasm(&mut objects, &mut game, &config);
+                fov = simulation_fov(&objects, &game);
+            } else {
+                let (player_x, player_y) = objects[PLAYER].pos();
+                fov.compute_fov(player_x, player_y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+            }
+        }
+
+        end_player_turn(&mut objects, &mut game, &fov);
+    }
+
+    SimulationReport {
+        seed,
+        turns: game.turns,
+        dungeon_level: game.dungeon_level,
+        player_level: objects[PLAYER].level,
+        outcome: if game.won {
+            "won".to_string()
+        } else if objects[PLAYER].alive {
+            "survived".to_string()
+        } else {
+            cause_of_death(&game)
+        },
+    }
+}
+
+fn simulation_fov(objects: &[Object], game: &Game) -> FovMap {
+    let (map_width, map_height) = map_size(&game.map);
+    let mut fov = FovMap::new(map_width, map_height);
+    fill_fov(&game.map, &mut fov);
+    let (player_x, player_y) = objects[PLAYER].pos();
+    fov.compute_fov(player_x, player_y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+    fov
+}
+
+// first move of a shortest walk over open floor, creatures ignored
+pub fn first_step(map: &Map, from: (i32, i32), to: (i32, i32)) -> Option<(i32, i32)> {
+    shortest_step(map, from, to, |tile| !tile.blocked)
+}
+
+// like first_step, but only over ground the player has already seen
+pub fn travel_step(map: &Map, from: (i32, i32), to: (i32, i32)) -> Option<(i32, i32)> {
+    shortest_step(map, from, to, |tile| !tile.blocked && !tile.chasm && tile.explored)
+}
+
+// what stepping onto a tile costs a monster finding its way, None where it can't go at all;
+// monsters know where every trap is, hidden or not
+pub fn tile_cost(tile: &Tile) -> Option<u32> {
+    if tile.blocked {
+        None
+    } else if tile.chasm || tile.trap.is_some() {
+        Some(HAZARD_COST)
+    } else {
+        Some(1)
+    }
+}
+
+// first move of the cheapest walk, diagonals included, with what each tile costs to enter
+pub fn cheapest_step(
+    map: &Map,
+    from: (i32, i32),
+    to: (i32, i32),
+    cost: impl Fn(&Tile) -> Option<u32>,
+) -> Option<(i32, i32)> {
+    let (map_width, map_height) = map_size(map);
+    let index = |(x, y): (i32, i32)| (x * map_height + y) as usize;
+    let neighbours = |(x, y): (i32, i32)| {
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+            .filter(move |&(nx, ny)| (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < map_width && ny < map_height)
+    };
+    let enter = |(x, y): (i32, i32)| cost(&map[x as usize][y as usize]);
+
+    // searched back from the goal like shortest_step, so each tile ends up with what getting from it to the goal costs
+    let mut total = vec![u32::MAX; (map_width * map_height) as usize];
+    let mut queue = BinaryHeap::new();
+    total[index(to)] = 0;
+    queue.push(Reverse((0, to)));
+
+    while let Some(Reverse((spent, tile))) = queue.pop() {
+        if tile == from {
+            // of the equally cheap ways on, take the one heading most directly for the goal
+            let distance = |(x, y): (i32, i32)| (x - to.0).pow(2) + (y - to.1).pow(2);
+            return neighbours(from)
+                .filter(|&next| enter(next).is_some_and(|step| total[index(next)].saturating_add(step) == spent))
+                .min_by_key(|&next| distance(next))
+                .map(|(next_x, next_y)| (next_x - from.0, next_y - from.1));
+        }
+        if spent > total[index(tile)] {
+            continue;
+        }
+        let through = match enter(tile) {
+            Some(step) => spent + step,
+            None => continue,
+        };
+        for next in neighbours(tile) {
+            // the walker's own tile is where it already stands, whatever it is
+            if next != from && enter(next).is_none() {
+                continue;
+            }
+            if through < total[index(next)] {
+                total[index(next)] = through;
+                queue.push(Reverse((through, next)));
+            }
+        }
+    }
+    None
+}
+
+fn shortest_step(map: &Map, from: (i32, i32), to: (i32, i32), passable: impl Fn(&Tile) -> bool) -> Option<(i32, i32)> {
+    let (map_width, map_height) = map_size(map);
+    let index = |(x, y): (i32, i32)| (x * map_height + y) as usize;
+
+    // search back from the goal so each tile knows its next step towards it
+    let mut next = vec![None; (map_width * map_height) as usize];
+    let mut queue = VecDeque::new();
+    next[index(to)] = Some(to);
+    queue.push_back(to);
+
+    while let Some((x, y)) = queue.pop_front() {
+        if (x, y) == from {
+            return next[index(from)].map(|(next_x, next_y)| (next_x - x, next_y - y));
+        }
+        for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= map_width || ny >= map_height {
+                continue;
+            }
+            if !passable(&map[nx as usize][ny as usize]) || next[index((nx, ny))].is_some() {
+                continue;
+            }
+            next[index((nx, ny))] = Some((x, y));
+            queue.push_back((nx, ny));
+        }
+    }
+    None
+}
@@ -1,528 +1,417 @@
-#[macro_use]
-extern crate serde_derive;
-
-use std::io::{Read, Write};
-use std::fs::File;
-use std::error::Error;
-
+use std::cell::RefCell;
 use std::cmp;
-use rand::{Rng};
-use rand::distributions::{WeightedIndex, Distribution};
+use std::collections::{HashMap, VecDeque};
+use std::panic;
 
 use tcod::colors::{self, Color};
 use tcod::console::*;
-use tcod::map::{FovAlgorithm, Map as FovMap};
-use tcod::input::{self, Event, Key, Mouse};
-
-const SCREEN_WIDTH: i32 = 80;
-const SCREEN_HEIGHT: i32 = 50;
-const LIMIT_FPS: i32 = 20;
-
-const MAP_WIDTH: i32 = 80;
-const MAP_HEIGHT: i32 = 43;
+use tcod::map::Map as FovMap;
+use tcod::input::{self, Event, Key, KeyCode, Mouse};
 
-const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-const COLOR_LIGHT_WALL: Color = Color {r: 130, g: 110, b: 50};
-const COLOR_DARK_GROUND: Color = Color {r: 50, g: 50, b: 150};
-const COLOR_LIGHT_GROUND: Color = Color {r: 200, g: 180, b: 50};
+use roguelike::*;
 
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
+const SIMULATION_TURNS: u64 = 5000;
 
-const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
-const FOV_LIGHT_WALLS: bool = true;
-const TORCH_RADIUS: i32 = 5;
-
-const PLAYER: usize = 0;
-
-const BAR_WIDTH: i32 = 20;
-const PANEL_HEIGHT: i32 = 7;
-const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 const MSG_X: i32 = BAR_WIDTH + 2;
-const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 
-const INVENTORY_WIDTH:i32 = 50;
+const INVENTORY_WIDTH:i32 = 70;
+// one letter per option, longer lists are split over several pages
+const MENU_PAGE_SIZE: usize = 26;
 
-const HEAL_AMOUNT:i32 = 40;
-const ATTACK_BUFF:i32 = 2;
-const PLAYER_MAX_ATTACK:i32 = 9;
 const LIGHTNING_DAMAGE:i32 = 40;
 const LIGHTNING_RANGE:i32 = 5;
 
-const LEVEL_UP_BASE: i32 = 200;
-const LEVEL_UP_FACTOR: i32 = 150;
+const ARROW_DAMAGE:i32 = 8;
+const ARROW_RANGE: f32 = 8.0;
+const THROW_RANGE: f32 = 5.0;
+
+const PROJECTILE_FRAMES: usize = 6;
+// how strongly reachable tiles and blast areas are tinted while aiming
+const TARGET_TINT: f32 = 0.3;
+// damage numbers climb a tile every few frames and are gone after a second or so
+const DAMAGE_LABEL_FRAMES: usize = 16;
+const DAMAGE_LABEL_RISE: usize = 6;
+const MOVE_FRAMES: usize = 2;
+// what the settings menu steps through, the settings file takes any value
+const FPS_CHOICES: &[i32] = &[10, 20, 30, 60];
+const ANIMATION_SPEEDS: &[f32] = &[0.5, 1.0, 1.5, 2.0, 3.0];
 
-const MONSTER_LEVEL_UP_BASE: u32 = 20;
-const MONSTER_ATTACK_LEVEL_UP_BASE: u32 = 5;
-const MONSTER_DEFENSE_LEVEL_UP_BASE: u32 = 5;
-const MONSTER_LEVEL_UP_FACTOR: i32 = 2;
+const CLAIRVOYANCE_RADIUS: i32 = 4;
+
+// map tiles per minimap cell, along each axis
+const MINIMAP_SCALE: i32 = 2;
 
 const LEVEL_SCREEN_WIDTH: i32 = 40;
 const CHARACTER_SCREEN_WIDTH: i32 = 30;
+const SETTINGS_SCREEN_WIDTH: i32 = 40;
+const HELP_SCREEN_WIDTH: i32 = 50;
+const RECORDS_SCREEN_WIDTH: i32 = 60;
+const RECORDS_SHOWN: usize = 10;
 
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Object {
-    x: i32,
-    y: i32,
-    char: char,
-    color: Color,
-    name: String,
-    blocks: bool,
-    alive: bool,
-    fighter: Option<Fighter>,
-    ai: Option<Ai>,
-    item:Option<Item>,
-    level: i32,
-    equipment: Option<Equipment>,
-    always_visible: bool,
-}
+const SAVE_SLOT: &str = "Savegame";
+const MENU_BACKGROUND: &str = "menu_background.png";
 
 struct Tcod {
     root: Root,
     con: Offscreen,
     panel: Offscreen,
     fov: FovMap,
-    mouse: Mouse
+    mouse: Mouse,
+    config: Config,
+    settings: Settings,
+    // where a mouse click asked the player to walk to
+    travel: Option<(i32, i32)>,
+    // map position shown at the top-left of the screen, set by render_all
+    camera: (i32, i32),
+    // tiles shown as if in view on top of the fov, only while a clairvoyance scroll is being read
+    revealed: Vec<(i32, i32)>,
+    // how far along the monsters' last steps are drawn, only set while animating them
+    tween: Option<f32>,
+    // inventory position last picked from each inventory menu, highlighted when it opens again
+    inventory_cursors: HashMap<InventoryMenu, usize>,
+    // developer keys such as the map dump, switched on with --debug
+    debug: bool,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Game {
-    map: Map,
-    log: Messages,
-    inventory: Vec<Object>,
-    dungeon_level: u32,
-}
-
-struct Transition {
-    level: u32,
-    value: u32,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-struct Equipment {
-    slot: Slot,
-    equipped: bool,
-    max_hp_bonus: i32,
-    power_bonus: i32,
-    defense_bonus: i32,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Slot {
-    RightHand,
-    LeftHand,
-    Chest
-}
-
-impl std::fmt::Display for Slot {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            Slot::LeftHand => write!(f, "left hand"),
-            Slot::RightHand => write!(f, "right hand"),
-            Slot::Chest => write!(f, "chest"),
-        }
+impl Tcod {
+    // the map tile under the mouse cursor
+    fn mouse_tile(&self) -> (i32, i32) {
+        (self.mouse.cx as i32 + self.camera.0, self.mouse.cy as i32 + self.camera.1)
     }
 }
 
-trait MessageLog {
-    fn add<T: Into<String>>(&mut self, message: T, color: Color);
+thread_local! {
+    static INPUT_LOG: RefCell<InputLog> = RefCell::new(InputLog::default());
 }
 
-impl MessageLog for Vec<(String, Color)> {
-    fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.push((message.into(), color));
-    }
+#[derive(Default)]
+struct InputLog {
+    // the run being written down, from the seed on
+    recording: Option<Replay>,
+    // inputs still to feed in instead of the keyboard and mouse
+    playback: VecDeque<RecordedInput>,
+    // the frame rate to go back to once the playback is over
+    resume_fps: i32,
 }
 
-impl Object {
-
-    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool,) -> Self {
-        Object{
-            x,
-            y,
-            char,
-            color,
-            name: name.into(),
-            blocks,
-            alive:false,
-            fighter: None,
-            ai: None,
-            item: None,
-            level: 1,
-            equipment: None,
-            always_visible: false,
-        }
-    }
-
-    pub fn draw(&self, con: &mut dyn Console){
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
-    }
-
-    pub fn pos(&self) -> (i32, i32){
-        (self.x, self.y)
-    }
-
-    pub fn set_pos(&mut self, x: i32, y: i32){
-        self.x = x;
-        self.y = y;
-    }
-
-    pub fn distance_to(&self, other: &Object) -> f32 {
-        let dx = other.x - self.x;
-        let dy = other.y - self.y;
-        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
-    }
-
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
-
-        //borrowed
-        if let Some(fighter) = self.fighter.as_mut() {
-            if damage > 0 {
-                fighter.hp -= damage;
+impl InputLog {
+    fn next_played_back(&mut self) -> Option<RecordedInput> {
+        if let Some(RecordedInput::Idle(frames)) = self.playback.front_mut() {
+            if *frames > 1 {
+                *frames -= 1;
+                return Some(RecordedInput::Idle(1));
             }
         }
-
-        //Copy
-        if let Some(fighter) = self.fighter {
-            if fighter.hp <= 0 {
-                self.alive = false;
-                fighter.on_death.callback(self, game);
-                return Some(fighter.xp);
-            }
+        let input = self.playback.pop_front();
+        if input.is_some() && self.playback.is_empty() {
+            // caught up, the player takes over at the usual pace
+            tcod::system::set_fps(self.resume_fps);
         }
-        None
+        input
     }
 
-    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-
-        let mut damage = self.power(game) - target.defense(game);
-
-        if rand::random::<f32>() <0.1 {
-            damage = -1;
-        }
-
-        if damage > 0 {
-            game.log.add(format!("{} attacks {} for {} hit points.", self.name, target.name, damage), colors::WHITE);
-
-            if let Some(xp) = target.take_damage(damage, game) {
-                self.fighter.as_mut().unwrap().xp += xp;
-            }
-
-        } else if damage < 0 {
-            game.log.add(format!("{} miss {}.", self.name, target.name), colors::ORANGE);
-        } else {
-            game.log.add(format!("{} attacks {} but it has no effect!",self.name, target.name), colors::WHITE);
+    fn record(&mut self, input: RecordedInput) {
+        if let Some(ref mut replay) = self.recording {
+            replay.record(input);
         }
     }
+}
 
-    pub fn cast(&mut self, _tcod: &mut Tcod, cast_type: &str, amount: i32, game: &mut Game) {
-        let max_hp = self.max_hp(game);
-        match cast_type.as_ref() {
-
-            "heal" =>
-
-                if let Some(ref mut fighter) = self.fighter {
-                    fighter.hp += amount;
-                    if fighter.hp > max_hp {
-                        fighter.hp = max_hp;
-                    }
-                }
-
-            _ => ()
-
-        }
-    }
-
-    pub fn equip (&mut self, log: &mut Vec<(String, Color)> ){
-
-        if self.item.is_none(){
-            log.add(
-                format!("Can't equip {:?} because it's not an Item.", self),
-                colors::RED,
-            );
-            return;
-        }
+// the key codes the game reacts to, anything else plays back as a plain character
+const RECORDED_KEY_CODES: &[KeyCode] = &[
+    KeyCode::Escape,
+    KeyCode::Enter,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Spacebar,
+    KeyCode::Tab,
+];
+
+fn recorded_key(key: Key) -> RecordedInput {
+    RecordedInput::Key { code: key.code as u32, printable: key.printable, alt: key.alt, ctrl: key.ctrl, shift: key.shift }
+}
 
-        if let Some(ref mut equipment) = self.equipment {
-            if !equipment.equipped {
-                equipment.equipped = true;
-                log.add(
-                    format!("Equipped {} on {:?}.", self.name, equipment.slot),
-                    colors::LIGHT_GREEN,
-                );
-            }
-        } else {
-            log.add(
-                format!("Can't equip {:?} because it's not an Equipment.", self),
-                colors::RED,
-            );
-        }
+fn recorded_event(event: Option<Event>) -> RecordedInput {
+    match event {
+        Some(Event::Key(key)) => recorded_key(key),
+        Some(Event::Mouse(m)) => RecordedInput::Mouse {
+            cx: m.cx as i32,
+            cy: m.cy as i32,
+            lbutton_pressed: m.lbutton_pressed,
+            rbutton_pressed: m.rbutton_pressed,
+        },
+        None => RecordedInput::Idle(1),
     }
+}
 
-    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) {
-        if self.item.is_none() {
-            log.add(
-                format!("Can't unequip {:?} because it's not an Item.", self),
-                colors::RED,
-            );
-            return;
-        };
-        if let Some(ref mut equipment) = self.equipment {
-            if equipment.equipped {
-                equipment.equipped = false;
-                log.add(
-                    format!("Unequipped {} from {:?}.", self.name, equipment.slot),
-                    colors::LIGHT_YELLOW,
-                );
-            }
-        } else {
-            log.add(
-                format!("Can't unequip {:?} because it's not an Equipment.", self),
-                colors::RED,
-            );
+fn played_back_event(input: RecordedInput) -> Option<Event> {
+    match input {
+        RecordedInput::Key { code, printable, alt, ctrl, shift } => {
+            let mut key = Key::default();
+            key.code = RECORDED_KEY_CODES.iter().cloned().find(|&c| c as u32 == code).unwrap_or(KeyCode::Char);
+            key.printable = printable;
+            key.pressed = true;
+            key.alt = alt;
+            key.ctrl = ctrl;
+            key.shift = shift;
+            Some(Event::Key(key))
         }
+        RecordedInput::Mouse { cx, cy, lbutton_pressed, rbutton_pressed } => Some(Event::Mouse(Mouse {
+            cx: cx as isize,
+            cy: cy as isize,
+            lbutton_pressed,
+            rbutton_pressed,
+            ..Default::default()
+        })),
+        RecordedInput::Idle(_) => None,
     }
+}
 
-    pub fn power(&self, game: &Game) -> i32 {
-        let base_power = self.fighter.map_or(0, |f| f.base_power);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.power_bonus)
-            .sum();
-        base_power + bonus
-    }
-
-    pub fn defense(&self, game: &Game) -> i32 {
-        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.defense_bonus)
-            .sum();
-        base_defense + bonus
+// every key and click the game reads comes through here or wait_for_keypress,
+// so a run can be written down as it's played and fed back in later
+fn check_for_event() -> Option<Event> {
+    if let Some(input) = INPUT_LOG.with(|log| log.borrow_mut().next_played_back()) {
+        return played_back_event(input);
     }
+    let event = input::check_for_event(input::MOUSE | input::KEY_PRESS).map(|(_, event)| event);
+    INPUT_LOG.with(|log| log.borrow_mut().record(recorded_event(event)));
+    event
+}
 
-    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
-        if self.name == "player" {
-            game.inventory
-                .iter()
-                .filter(|item| item.equipment.map_or(false, |e| e.equipped))
-                .map(|item| item.equipment.unwrap())
-                .collect()
-        } else {
-            vec![] //noequippement objects
+fn wait_for_keypress(root: &mut Root) -> Key {
+    while let Some(input) = INPUT_LOG.with(|log| log.borrow_mut().next_played_back()) {
+        if let Some(Event::Key(key)) = played_back_event(input) {
+            return key;
         }
     }
-
-    pub fn max_hp(&self, game: &Game) -> i32 {
-        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(|e| e.max_hp_bonus)
-            .sum();
-        base_max_hp + bonus
-    }
-
+    let key = root.wait_for_keypress(true);
+    INPUT_LOG.with(|log| log.borrow_mut().record(recorded_key(key)));
+    key
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-struct Tile {
-    blocked: bool,
-    block_sight: bool,
-    explored: bool,
+fn start_recording(config: Config) {
+    let seed = new_seed();
+    seed_rng(seed);
+    INPUT_LOG.with(|log| log.borrow_mut().recording = Some(Replay::new(seed, config)));
 }
 
-impl Tile {
-    pub fn empty() -> Self{
-        Tile{blocked: false, block_sight: false, explored: false}
+fn finish_recording() -> Result<(), Box<dyn std::error::Error>> {
+    match INPUT_LOG.with(|log| log.borrow_mut().recording.take()) {
+        Some(replay) => save_replay(&replay, REPLAY_FILE),
+        None => Ok(()),
     }
-
-    pub fn wall() -> Self{
-        Tile{blocked: true, block_sight: true, explored: false}
-    }
-}
-
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-enum Item {
-    Heal,
-    AttackBuff,
-    Lightning,
-    Sword,
-    Chest,
-    Targe,
-}
-
-enum UseResult {
-    UsedUp,
-    Cancelled,
-    UseAndKept,
-    UseAndTakeTurn
 }
 
-type Map = Vec<Vec<Tile>>;
-type Messages = Vec<(String, Color)>;
-
-
-fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut[Object]){
-
-    let (x,y) = objects[id].pos();
-
-    if !is_blocked(x + dx, y + dy, map, objects){
-        objects[id].set_pos(x + dx, y + dy);
+fn start_playback(replay: Replay, resume_fps: i32) {
+    seed_rng(replay.seed);
+    if !replay.inputs.is_empty() {
+        // no need to sit through the run at the pace it was played
+        tcod::system::set_fps(0);
     }
-
-}
-
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
-    table
-        .iter()
-        .rev()
-        .find(|transition| level >= transition.level)
-        .map_or(0, |transition| transition.value)
+    INPUT_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        log.playback = replay.inputs.into();
+        log.resume_fps = resume_fps;
+    });
 }
 
-fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
-    let dx = target_x - objects[id].x;
-    let dy = target_y - objects[id].y;
-    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
-
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
-
-    move_by(id, dx, dy, map, objects);
+// a crash is the run most worth keeping, so it's written out before unwinding
+fn save_recording_on_panic() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let replay = INPUT_LOG.with(|log| log.try_borrow_mut().ok().and_then(|mut log| log.recording.take()));
+        if let Some(replay) = replay {
+            let _ = save_replay(&replay, REPLAY_FILE);
+        }
+        default_hook(info);
+    }));
 }
 
-fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
-    assert_ne!(first_index, second_index);
-
-    let split_at_index = cmp::max(first_index, second_index);
-    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
-
-    if first_index < second_index {
-        (&mut first_slice[first_index], &mut second_slice[0])
-    } else {
-        (&mut second_slice[0], &mut first_slice[second_index])
-    }
+fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize>{
+    cursor_menu(header, options, width, root, None)
 }
 
-fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize>{
-    assert!(
-        options.len() <= 27,
-        "Cannot have a menu with more than 26 options."
-    );
+// a menu with one option highlighted, which the up and down keys move and enter picks
+fn cursor_menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root, mut cursor: Option<usize>) -> Option<usize>{
+    let pages = cmp::max(1, options.len().div_ceil(MENU_PAGE_SIZE));
+    let mut page = cursor.map_or(0, |cursor| cursor / MENU_PAGE_SIZE);
 
     let header_height = if header.is_empty() {
         0
     } else {
-        root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
+        root.get_height_rect(0, 0, width, root.height(), header)
     };
-    let height = options.len() as i32 + header_height;
-
-    let mut window = Offscreen::new(width,  height);
-    window.set_default_foreground(colors::WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
-    );
-
-    for(index, option_text) in options.iter().enumerate() {
-        let menu_letter = (b'a' + index as u8) as char;
-        let text = format!("[{}] - {}", menu_letter, option_text.as_ref());
-        window.print_ex(
+    // every page is as tall as the first so flipping covers the previous one
+    let rows = cmp::min(options.len(), MENU_PAGE_SIZE) as i32;
+    let footer_height = if pages > 1 { 1 } else { 0 };
+    let height = rows + header_height + footer_height;
+
+    loop {
+        let start = page * MENU_PAGE_SIZE;
+        let shown = &options[start..cmp::min(start + MENU_PAGE_SIZE, options.len())];
+
+        let mut window = Offscreen::new(width,  height);
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(
             0,
-            header_height + index as i32,
+            0,
+            width,
+            height,
             BackgroundFlag::None,
             TextAlignment::Left,
-            text,
+            header,
         );
-    }
 
-    let x = SCREEN_WIDTH / 2 - width / 2;
-    let y = SCREEN_HEIGHT / 2 - height / 2;
+        for(index, option_text) in shown.iter().enumerate() {
+            let menu_letter = (b'a' + index as u8) as char;
+            let text = format!("[{}] - {}", menu_letter, option_text.as_ref());
+            window.print_ex(
+                0,
+                header_height + index as i32,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                text,
+            );
+            if cursor == Some(start + index) {
+                for x in 0..width {
+                    window.set_char_background(x, header_height + index as i32, colors::DARK_GREY, BackgroundFlag::Set);
+                }
+            }
+        }
 
-    tcod::console::blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
-    root.flush();
-    let key = root.wait_for_keypress(true);
+        if pages > 1 {
+            window.print_ex(
+                0,
+                height - 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                format!("[space/arrows] - page {} of {}", page + 1, pages),
+            );
+        }
+
+        let x = root.width() / 2 - width / 2;
+        let y = root.height() / 2 - height / 2;
+
+        tcod::console::blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+        let key = wait_for_keypress(root);
+
+        if let Some(current) = cursor {
+            match key.code {
+                input::KeyCode::Enter => return Some(current),
+                input::KeyCode::Up | input::KeyCode::Down => {
+                    let step = if key.code == input::KeyCode::Up { options.len() - 1 } else { 1 };
+                    let next = (current + step) % options.len();
+                    cursor = Some(next);
+                    page = next / MENU_PAGE_SIZE;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        if pages > 1 {
+            let flipped = match key.code {
+                input::KeyCode::Spacebar | input::KeyCode::Right => Some((page + 1) % pages),
+                input::KeyCode::Left => Some((page + pages - 1) % pages),
+                _ => None,
+            };
+            if let Some(flipped) = flipped {
+                page = flipped;
+                // keep the highlight on the page being shown so enter can't pick a hidden option
+                cursor = cursor.map(|current| cmp::min(page * MENU_PAGE_SIZE + current % MENU_PAGE_SIZE, options.len() - 1));
+                continue;
+            }
+        }
 
-    if key.printable.is_alphabetic(){
-        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
-            Some(index)
+        return if key.printable.is_alphabetic(){
+            let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+            if index < shown.len() {
+                Some(start + index)
+            }else{
+                None
+            }
         }else{
             None
-        }
-    }else{
-        None
+        };
+    }
+}
+
+fn pickup_menu(item_ids: &[usize], objects: &[Object], game: &Game, root: &mut Root) -> Vec<usize> {
+    let mut options: Vec<String> = item_ids.iter().map(|&id| game.display_name(&objects[id])).collect();
+    options.push("All of it".into());
+
+    match menu("Pick up which item?\n", &options, INVENTORY_WIDTH, root) {
+        Some(choice) if choice < item_ids.len() => vec![item_ids[choice]],
+        Some(_) => item_ids.to_vec(),
+        None => vec![],
     }
+}
 
+// each way of picking from the inventory remembers its own last choice
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum InventoryMenu {
+    Use,
+    Drop,
+    Examine,
+    Identify,
+    Recharge,
 }
 
-fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
-    let options = if inventory.len() == 0 {
+// the menu lists items by category, so its index is mapped back to the inventory position
+fn inventory_menu(game: &Game, kind: InventoryMenu, header: &str, tcod: &mut Tcod) -> Option<usize> {
+    let inventory = &game.inventory;
+    let mut order: Vec<usize> = (0..inventory.len()).collect();
+    order.sort_by_key(|&id| {
+        let item = &inventory[id];
+        let equipped = item.equipment.is_some_and(|e| e.equipped);
+        (item.category(), !equipped)
+    });
+
+    let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
-        inventory
+        order
             .iter()
+            .map(|&id| &inventory[id])
             .map(|item| {
                 match item.equipment {
                     Some(equipment) if equipment.equipped => {
                         format!("{} (on {})", item.name, equipment.slot)
                     }
-                    _ => item.name.clone(),
+                    Some(_) => {
+                        format!("{}: {}", item.name, equipment_comparison(item, game))
+                    }
+                    _ if item.charges.is_some() => {
+                        format!("{} ({} charges)", game.display_name(item), item.charges.unwrap_or(0))
+                    }
+                    _ if item.quantity > 1 => {
+                        format!("{} (x{})", game.display_name(item), item.quantity)
+                    }
+                    _ => game.display_name(item),
                 }
             })
             .collect()    };
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
-
-    if inventory.len() > 0 {
-        inventory_index
-    } else {
-        None
+    if inventory.is_empty() {
+        menu(header, &options, INVENTORY_WIDTH, &mut tcod.root);
+        return None;
     }
-}
-
-fn cast_heal(tcod: &mut Tcod,_inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult{
-
-    let player = &mut objects[PLAYER];
 
-    if let Some(fighter) = player.fighter {
-        if fighter.hp == player.max_hp(game) {
-            game.log.add("You are already at full health.", colors::RED);
-            return UseResult::Cancelled;
-        }
-        game.log
-            .add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
-        objects[PLAYER].cast(tcod, "heal", HEAL_AMOUNT, game);
-        return UseResult::UsedUp;
+    // the remembered item may have been used up since, so land on whatever took its place
+    let cursor = tcod
+        .inventory_cursors
+        .get(&kind)
+        .map(|&id| cmp::min(id, inventory.len() - 1))
+        .and_then(|id| order.iter().position(|&other| other == id));
+    let chosen = cursor_menu(header, &options, INVENTORY_WIDTH, &mut tcod.root, cursor).map(|index| order[index]);
+    if let Some(chosen) = chosen {
+        tcod.inventory_cursors.insert(kind, chosen);
     }
-    UseResult::Cancelled
-}
-
-fn cast_attack_buff(tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult{
-
-    if let Some(fighter) = objects[PLAYER].fighter {
-        if fighter.base_power >= PLAYER_MAX_ATTACK {
-            game.log.add("Your attack lvl is too high for this item level", colors::RED);
-            return UseResult::Cancelled;
-        }
-        objects[PLAYER].cast(tcod, "attack_buff", ATTACK_BUFF, game);
-        game.log.add(format!("Permanently increase your attack by: {}", ATTACK_BUFF), colors::GREEN);
-
-        return UseResult::UsedUp;
-    }
-    UseResult::Cancelled
+    chosen
 }
 
 fn cast_lightning(
@@ -531,14 +420,16 @@ fn cast_lightning(
     objects: &mut [Object],
     game: &mut Game
 ) -> UseResult {
-    let monster_id = closest_monster(LIGHTNING_RANGE, objects, tcod);
+    let monster_id = closest_monster(LIGHTNING_RANGE, objects, &game.map, &tcod.fov);
     if let Some(monster_id) = monster_id {
 
+        animate_projectile(tcod, objects[PLAYER].pos(), objects[monster_id].pos(), '*', colors::LIGHT_BLUE);
         game.log.add(format!("A lightning bolt strikes the {} with a loud thunder! \
                  The damage is {} hit points.",
-            objects[monster_id].name, LIGHTNING_DAMAGE), colors::LIGHT_BLUE,);
+            objects[monster_id].name, objects[monster_id].resisted_damage(LIGHTNING_DAMAGE, DamageType::Lightning)), colors::LIGHT_BLUE,);
 
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
+        let caster = objects[PLAYER].pos();
+        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, DamageType::Lightning, Some(caster), game) {
             objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
         }
 
@@ -549,47 +440,210 @@ fn cast_lightning(
     }
 }
 
-fn toggle_equipment(_tcod: &mut Tcod, inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
-    let equipment = match game.inventory[inventory_id].equipment {
-        Some(equipment) => equipment,
+// the spell has no inventory slot, the cast functions never look at it
+fn cast_spell(tcod: &mut Tcod, spell: Spell, objects: &mut [Object], game: &mut Game) -> PlayerAction {
+    let mana = objects[PLAYER].fighter.map_or(0, |f| f.mana);
+    if mana < spell.mana_cost() {
+        game.log.add(format!("You need {} mana to cast {}.", spell.mana_cost(), spell), colors::RED);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    let result = match spell {
+        Spell::Lightning => cast_lightning(tcod, 0, objects, game),
+        Spell::Heal => cast_heal(0, objects, game),
+    };
+    match result {
+        UseResult::Cancelled => PlayerAction::DidntTakeTurn,
+        _ => {
+            if let Some(ref mut fighter) = objects[PLAYER].fighter {
+                fighter.mana -= spell.mana_cost();
+            }
+            PlayerAction::TookTurn
+        }
+    }
+}
+
+fn spell_menu(game: &Game, root: &mut Root) -> Option<Spell> {
+    let options: Vec<String> = game
+        .spells
+        .iter()
+        .map(|spell| format!("{} ({} mana)", spell, spell.mana_cost()))
+        .collect();
+    let header = if options.is_empty() {
+        "You don't know any spells.\n"
+    } else {
+        "Press the key next to a spell to cast it, or any other to cancel.\n"
+    };
+    let index = menu(header, &options, INVENTORY_WIDTH, root)?;
+    game.spells.get(index).cloned()
+}
+
+fn cast_clairvoyance(tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    game.log.add(
+        "Left-click a place you have explored to scry it, or right-click to cancel.",
+        colors::LIGHT_CYAN,
+    );
+    let (target_x, target_y) = match target_tile(tcod, objects, game, None, false, Some(CLAIRVOYANCE_RADIUS)) {
+        Some(tile) => tile,
         None => return UseResult::Cancelled,
     };
 
-    if equipment.equipped == true {
-        game.inventory[inventory_id].dequip(&mut game.log);
-    }else{
-        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
-            game.inventory[current].dequip(&mut game.log);
+    let (map_width, map_height) = map_size(&game.map);
+    tcod.revealed = (target_x - CLAIRVOYANCE_RADIUS..=target_x + CLAIRVOYANCE_RADIUS)
+        .flat_map(|x| (target_y - CLAIRVOYANCE_RADIUS..=target_y + CLAIRVOYANCE_RADIUS).map(move |y| (x, y)))
+        .filter(|&(x, y)| x >= 0 && y >= 0 && x < map_width && y < map_height)
+        .filter(|&(x, y)| (x - target_x).pow(2) + (y - target_y).pow(2) <= CLAIRVOYANCE_RADIUS.pow(2))
+        .collect();
+
+    game.log.add("Your sight drifts far from your body. Press any key to return.", colors::LIGHT_CYAN);
+    tcod.con.clear();
+    render_all(tcod, objects, game, false);
+    tcod.root.flush();
+    wait_for_keypress(&mut tcod.root);
+    tcod.revealed.clear();
+    UseResult::UsedUp
+}
+
+fn cast_identify(tcod: &mut Tcod, _inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
+    let chosen = inventory_menu(
+        game,
+        InventoryMenu::Identify,
+        "Choose an item to identify, or any other key to cancel.\n",
+        tcod,
+    );
+
+    match chosen {
+        Some(chosen) => {
+            let object = &game.inventory[chosen];
+            match object.item {
+                Some(item) if !object.identified && !game.is_identified(item) => {
+                    let appearance = game.display_name(object);
+                    game.identify(item);
+                    let name = game.inventory[chosen].name.clone();
+                    game.log.add(format!("The {} is a {}.", appearance, name), colors::LIGHT_VIOLET);
+                    UseResult::UsedUp
+                }
+                // gear is known by sight, only its bonuses are a mystery
+                Some(_) if !object.identified => {
+                    game.inventory[chosen].identified = true;
+                    let object = &game.inventory[chosen];
+                    let name = object.name.clone();
+                    let comparison = equipment_comparison(object, game);
+                    game.log.add(format!("The {}: {}.", name, comparison), colors::LIGHT_VIOLET);
+                    UseResult::UsedUp
+                }
+                _ => {
+                    game.log.add("You already know what that is.", colors::RED);
+                    UseResult::Cancelled
+                }
+            }
+        }
+        None => UseResult::Cancelled,
+    }
+}
+
+fn cast_recharge(tcod: &mut Tcod, _inventory_id: usize, _objects: &mut [Object], game: &mut Game) -> UseResult {
+    let chosen = inventory_menu(
+        game,
+        InventoryMenu::Recharge,
+        "Choose an item to recharge, or any other key to cancel.\n",
+        tcod,
+    );
+
+    match chosen {
+        Some(chosen) => {
+            let object = &mut game.inventory[chosen];
+            match (object.charges, object.item.and_then(Item::max_charges)) {
+                (Some(charges), Some(max_charges)) if charges < max_charges => {
+                    object.charges = Some(max_charges);
+                    let name = game.display_name(&game.inventory[chosen]);
+                    game.log.add(format!("The {} hums with renewed power.", name), colors::LIGHT_ORANGE);
+                    UseResult::UsedUp
+                }
+                (Some(_), Some(_)) => {
+                    game.log.add("That is already fully charged.", colors::RED);
+                    UseResult::Cancelled
+                }
+                _ => {
+                    game.log.add("That has no charges to restore.", colors::RED);
+                    UseResult::Cancelled
+                }
+            }
         }
-        game.inventory[inventory_id].equip(&mut game.log);
+        None => UseResult::Cancelled,
     }
+}
 
-    UseResult::UseAndKept
+fn shoot_arrow(tcod: &mut Tcod, _inventory_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    game.log.add(
+        "Left-click an enemy to shoot it, or right-click to cancel.",
+        colors::LIGHT_CYAN,
+    );
+    let monster_id = match target_monster(tcod, objects, game, Some(ARROW_RANGE)) {
+        Some(monster_id) => monster_id,
+        None => return UseResult::Cancelled,
+    };
+
+    animate_projectile(tcod, objects[PLAYER].pos(), objects[monster_id].pos(), '-', colors::LIGHT_SEPIA);
+    game.log.add(
+        format!(
+            "Your arrow hits the {} for {} hit points.",
+            objects[monster_id].name,
+            objects[monster_id].resisted_damage(ARROW_DAMAGE, DamageType::Physical)
+        ),
+        colors::LIGHT_CYAN,
+    );
+    let archer = objects[PLAYER].pos();
+    if let Some(xp) = objects[monster_id].take_damage(ARROW_DAMAGE, DamageType::Physical, Some(archer), game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+    UseResult::UseAndTakeTurn
 }
 
-fn get_equipped_in_slot (slot: Slot, inventory: &[Object]) -> Option<usize> {
-    for (inventory_id, item) in inventory.iter().enumerate() {
-        if item.equipment.as_ref().map_or(false,|e| e.equipped && e.slot == slot){
-            return Some(inventory_id)
+fn throw_equipped_weapon(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) -> PlayerAction {
+    let weapon_id = match equipped_weapon(&game.inventory) {
+        Some(weapon_id) => weapon_id,
+        None => {
+            game.log.add("You have no weapon in hand to throw.", colors::RED);
+            return PlayerAction::DidntTakeTurn;
         }
+    };
+    let weapon = &game.inventory[weapon_id];
+    if weapon.equipment.is_some_and(|e| e.cursed) {
+        game.log.add(format!("The {} won't leave your hand!", weapon.name), colors::RED);
+        return PlayerAction::DidntTakeTurn;
+    }
+    let glyph = weapon.char;
+    let color = weapon.color;
+
+    game.log.add(
+        "Left-click an enemy to throw your weapon at it, or right-click to cancel.",
+        colors::LIGHT_CYAN,
+    );
+    let monster_id = match target_monster(tcod, objects, game, Some(THROW_RANGE)) {
+        Some(monster_id) => monster_id,
+        None => return PlayerAction::DidntTakeTurn,
+    };
+
+    animate_projectile(tcod, objects[PLAYER].pos(), objects[monster_id].pos(), glyph, color);
+    if throw_weapon(weapon_id, monster_id, objects, game) {
+        PlayerAction::TookTurn
+    } else {
+        PlayerAction::DidntTakeTurn
     }
-    None
 }
 
 fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod){
     let player = &mut objects[PLAYER];
-    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
-
-    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
-        player.level += 1;
-        game.log.add(format!("You reached level {}!", player.level), colors::YELLOW);
 
-        let fighter = player.fighter.as_mut().unwrap();
+    if can_level_up(player) {
+        let fighter = player.fighter.unwrap();
+        let header = format!("Level up! Your gear gives you:\n{}\n\nChoose a stat to raise:\n", gear_lines(game));
         let mut choice = None;
         while choice.is_none() {
 
             choice = menu(
-                "Level up! Choose a stat to raise:\n",
+                &header,
                 &[
                     format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
                     format!("Strength (+1 attack, from {})", fighter.base_power),
@@ -599,74 +653,140 @@ fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod){
                 &mut tcod.root,
             );
         }
-        fighter.xp -= level_up_xp;
-        match choice.unwrap() {
-            0 => {
-                fighter.base_max_hp += 20;
-                fighter.hp += 20;
-            }
-            1 => {
-                fighter.base_power += 1;
-            }
-            2 => {
-                fighter.base_defense += 1;
-            }
-            _ => unreachable!(),
-        }
+        gain_level(player, choice.unwrap(), game);
+    }
+}
 
+fn gear_lines(game: &Game) -> String {
+    let lines = equipment_breakdown(game);
+    if lines.is_empty() {
+        "nothing".to_string()
+    } else {
+        lines.join("\n")
     }
 }
 
-fn closest_monster(max_range: i32, objects: &mut [Object], tcod: &Tcod) -> Option<usize> {
-    let mut closest_enemy = None;
-    let mut closest_dist = (max_range + 1) as f32;
+// in_sight limits the choice to tiles in view, otherwise any explored tile will do;
+// a blast radius is shown around the cursor for effects that cover an area
+fn target_tile(
+    tcod: &mut Tcod,
+    objects: &[Object],
+    game: &mut Game,
+    max_range: Option<f32>,
+    in_sight: bool,
+    blast_radius: Option<i32>,
+) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::Escape;
+    loop {
+        tcod.root.flush();
+        let mut key: Key = Default::default();
+        match check_for_event() {
+            Some(Event::Mouse(m)) => tcod.mouse = m,
+            Some(Event::Key(k)) => key = k,
+            _ => {}
+        }
+        tcod.con.clear();
+        render_all(tcod, objects, game, false);
 
-    for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER)
-            && object.fighter.is_some()
-            && object.ai.is_some()
-            && tcod.fov.is_in_fov(object.x, object.y)
-        {
-            let dist = objects[PLAYER].distance_to(object);
-            if dist < closest_dist {
-                closest_enemy = Some(id);
-                closest_dist = dist;
-            }
+        let (x, y) = tcod.mouse_tile();
+        let (map_width, map_height) = map_size(&game.map);
+        let on_map = x >= 0 && y >= 0 && (x < map_width) && (y < map_height);
+        let known = on_map && if in_sight {
+            tcod.fov.is_in_fov(x, y) && has_line_of_sight(&game.map, objects[PLAYER].pos(), (x, y))
+        } else {
+            game.map[x as usize][y as usize].explored
+        };
+        let in_range = max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range);
+        highlight_targets(tcod, objects, game, max_range, blast_radius, known && in_range);
+        if tcod.mouse.lbutton_pressed && known && in_range {
+            return Some((x, y));
+        }
+
+        if tcod.mouse.rbutton_pressed || key.code == Escape {
+            return None;
         }
     }
-    closest_enemy
 }
 
-fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) {
+// tints what the player could aim at and what the effect would cover, over the frame just rendered
+fn highlight_targets(
+    tcod: &mut Tcod,
+    objects: &[Object],
+    game: &Game,
+    max_range: Option<f32>,
+    blast_radius: Option<i32>,
+    cursor_usable: bool,
+) {
+    let (map_width, map_height) = map_size(&game.map);
+    let (cursor_x, cursor_y) = tcod.mouse_tile();
+    let camera = tcod.camera;
+
+    for screen_y in 0..tcod.con.height() {
+        for screen_x in 0..tcod.con.width() {
+            let (x, y) = (screen_x + camera.0, screen_y + camera.1);
+            if x < 0 || y < 0 || x >= map_width || y >= map_height || !game.map[x as usize][y as usize].explored {
+                continue;
+            }
+            let in_blast = cursor_usable
+                && blast_radius.is_some_and(|radius| (x - cursor_x).pow(2) + (y - cursor_y).pow(2) <= radius.pow(2));
+            let reachable = max_range.is_some_and(|range| {
+                tcod.fov.is_in_fov(x, y) && objects[PLAYER].distance(x, y) <= range
+            });
+            let tint = if in_blast {
+                colors::ORANGE
+            } else if reachable {
+                colors::LIGHT_BLUE
+            } else {
+                continue;
+            };
+            let background = tcod.root.get_char_background(screen_x, screen_y);
+            tcod.root.set_char_background(screen_x, screen_y, colors::lerp(background, tint, TARGET_TINT), BackgroundFlag::Set);
+        }
+    }
 
-    use Ai::*;
-    if let Some(ai) = objects[monster_id].ai.take() {
-        let new_ai = match ai {
-            Basic => ai_basic(monster_id, objects, fov_map, game),
-        };
-        objects[monster_id].ai = Some(new_ai);
+    let (screen_x, screen_y) = (cursor_x - camera.0, cursor_y - camera.1);
+    let on_view = screen_x >= 0 && screen_y >= 0 && screen_x < tcod.con.width() && screen_y < tcod.con.height();
+    if !cursor_usable && on_view {
+        tcod.root.set_char_background(screen_x, screen_y, colors::DARK_RED, BackgroundFlag::Set);
     }
 }
 
-fn ai_basic(
-    monster_id: usize,
-    objects: &mut [Object],
-    fov_map: &FovMap,
+fn target_monster(
+    tcod: &mut Tcod,
+    objects: &[Object],
     game: &mut Game,
-) -> Ai {
-    let (monster_x, monster_y) = objects[monster_id].pos();
-    if fov_map.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, game);
+    max_range: Option<f32>,
+) -> Option<usize> {
+    loop {
+        match target_tile(tcod, objects, game, max_range, true, None) {
+            Some((x, y)) => {
+                for (id, obj) in objects.iter().enumerate() {
+                    if obj.occupies(x, y) && obj.fighter.is_some() && id != PLAYER {
+                        return Some(id);
+                    }
+                }
+            }
+            None => return None,
         }
     }
-    Ai::Basic
 }
 
+// draws the glyph moving along the line over the last rendered frame
+fn animate_projectile(tcod: &mut Tcod, from: (i32, i32), to: (i32, i32), glyph: char, color: Color) {
+    let path = line_points(from, to);
+    let frames = tcod.settings.animation_frames(PROJECTILE_FRAMES);
+    let step = cmp::max(1, path.len().div_ceil(frames));
+
+    for &(x, y) in path.iter().step_by(step) {
+        let size = (tcod.con.width(), tcod.con.height());
+        blit(&tcod.con, (0, 0), size, &mut tcod.root, (0, 0), 1.0, 1.0);
+        tcod.root.set_default_foreground(color);
+        tcod.root.put_char(x - tcod.camera.0, y - tcod.camera.1, glyph, BackgroundFlag::None);
+        tcod.root.flush();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn render_bar(
     panel: &mut Offscreen,
     x: i32,
@@ -694,464 +814,80 @@ fn render_bar(
         y,
         BackgroundFlag::None,
         TextAlignment::Center,
-        &format!("{}: {}/{}", name, value, maximum),
+        format!("{}: {}/{}", name, value, maximum),
     );
 }
 
-fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-
-    if map[x as usize][y as usize].blocked {
-        return true;
-    }
-
-    objects.iter().any(|object |{
-        object.blocks && object.pos() == (x, y)
-    })
-
-}
-
-fn pick_item_up(object_id:usize, objects: &mut Vec<Object>, game: &mut Game){
-    if game.inventory.len() >= 26 {
-        game.log.add(format!("Your inventory is full, you cannot pick up {}",objects[object_id].name),colors::RED);
-
-    }else{
-        let item = objects.swap_remove(object_id);
-        game.log.add(format!("You pick up a {}", item.name),colors::GREEN);
-
-        game.inventory.push(item);
-    }
-}
-
-fn drop_item(inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game){
-
-    let mut item = game.inventory.remove(inventory_id);
-    if item.equipment.is_some() {
-        item.dequip(&mut game.log);
-    }
-    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
-    game.log.add(format!("You dropped a {}", item.name), colors::YELLOW);
-    objects.push(item);
-}
-
-#[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
-}
-
-impl Rect {
-    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect {
-            x1: x,
-            y1: y,
-            x2: x + w,
-            y2: y + h,
-        }
-    }
-
-    pub fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
-        (center_x, center_y)
-    }
-
-    pub fn intersect_with(&self, other: &Rect) -> bool {
-        (self.x1 <= other.x2)
-            && (self.x2 >= other.x1)
-            && (self.y1 <= other.y2)
-            && (self.y2 >= other.y1)
-    }
-}
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum PlayerAction {
     TookTurn,
     DidntTakeTurn,
     Exit,
+    Abandon,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum DeathCallback {
-    Player,
-    Monster,
-}
-
-impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
-        use DeathCallback::*;
-        let callback: fn(&mut Object, game: &mut Game) = match self {
-            Player => player_death,
-            Monster => monster_death,
-        };
-
-        callback(object, game);
-    }
-}
-
-fn player_death(player: &mut Object, game: &mut Game) {
-
-    game.log.add("You died, see you another time!", colors::RED);
-    player.char = '%';
-    player.color = colors::LIGHTER_RED;
-}
-
-fn monster_death(monster: &mut Object, game: &mut Game) {
-
-    game.log.add(format!("PAF! {} is dead! You gain {}", monster.name, monster.fighter.unwrap().xp), colors::ORANGE);
-    monster.char = '%';
-    monster.color = colors::DARK_RED;
-    monster.blocks = false;
-    monster.fighter = None;
-    monster.ai = None;
-    monster.name = format!("Remains of {}", monster.name);
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-struct Fighter {
-    base_max_hp: i32,
-    hp: i32,
-    base_defense: i32,
-    base_power: i32,
-    on_death: DeathCallback,
-    xp: i32,
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Ai {
-    Basic
-}
+fn use_item (tcod: &mut Tcod, inventory_id: usize, object: &mut [Object], game: &mut Game) -> PlayerAction{
+    use UseCallback::*;
+    use PlayerAction::*;
 
-fn create_room(room: Rect, map: &mut Map)
-{
-    for x in (room.x1 + 1)..room.x2{
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+    if let Some(item) = game.inventory[inventory_id].item {
+        // only once the player knows what it is, asking would give unidentified ones away
+        let known = game.inventory[inventory_id].identified || game.is_identified(item);
+        if known && item.is_rare() {
+            let name = game.display_name(&game.inventory[inventory_id]);
+            if !confirm(&format!("Use the {}?", name), &mut tcod.root) {
+                return DidntTakeTurn;
+            }
         }
-    }
-}
-
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
-}
-
-fn place_object(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32){
-
-    let max_monsters = from_dungeon_level(
-        &[
-            Transition { level: 1, value: 2 },
-            Transition { level: 4, value: 3 },
-            Transition { level: 6, value: 5 },
-            Transition { level: 10, value: 7 },
-        ],
-        level,
-    );
-
-    let poulet_chance = from_dungeon_level(
-        &[
-            Transition {level: 1, value: 60,},
-            Transition {level: 2, value: 30,},
-            Transition {level: 4, value: 0,},
-        ],
-        level,
-    );
-
-    let orc_chance = from_dungeon_level(
-        &[
-            Transition {level: 2, value: 30,},
-            Transition {level: 5, value: 30,},
-        ],
-        level,
-    );
-
-    let troll_chance = from_dungeon_level(
-        &[
-            Transition {level: 4, value: 15,},
-            Transition {level: 5, value: 30,},
-            Transition {level: 7, value: 60,},
-        ],
-        level,
-    );
-
-    let boss_chance = from_dungeon_level(
-        &[
-            Transition {level: 3, value: 10,},
-            Transition {level: 5, value: 15,},
-            Transition {level: 7, value: 20,},
-        ],
-        level,
-    );
-
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
-
-    for _ in 0..num_monsters {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
 
-
-        let choices = ["poulet","orc", "troll", "boss"];
-        let weights = [poulet_chance, orc_chance,   troll_chance,   boss_chance];
-        let monster_choice = WeightedIndex::new(&weights).unwrap();
-
-
-        if !is_blocked(x, y, map, objects){
-
-            let mut monster = match choices[monster_choice.sample(&mut rand::thread_rng())] {
-                "orc" => {
-                    let mut orc= Object::new(x, y, 'o', "orc", colors::LIGHT_GREEN, true);
-                    let hp_multiplier = ((MONSTER_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let attack_multiplier = ((MONSTER_ATTACK_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let defense_multiplier = ((MONSTER_DEFENSE_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    orc.fighter = Some(Fighter {
-                        base_max_hp: 10 + hp_multiplier as i32,
-                        hp: 10 + hp_multiplier as i32,
-                        base_defense: defense_multiplier,
-                        base_power: 4 + attack_multiplier as i32,
-                        on_death: DeathCallback::Monster,
-                        xp:35
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                }
-                "poulet" => {
-                    let mut poulet= Object::new(x, y, 'p', "poulet", colors::GREY, true);
-                    poulet.fighter = Some(Fighter {
-                        base_max_hp: 15,
-                        hp: 15,
-                        base_defense: 0,
-                        base_power: 3,
-                        on_death: DeathCallback::Monster,
-                        xp:20
-                    });
-                    poulet.ai = Some(Ai::Basic);
-                    poulet
-                }
-                "troll" => {
-                    let hp_multiplier = ((MONSTER_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let attack_multiplier = ((MONSTER_ATTACK_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let defense_multiplier = ((MONSTER_DEFENSE_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let mut troll = Object::new(x, y, 'T', "troll", colors::LIGHT_GREEN, true);
-                    troll.fighter = Some(Fighter {
-                        base_max_hp: 15 + hp_multiplier as i32,
-                        hp: 15 + hp_multiplier as i32,
-                        base_defense: 1 + defense_multiplier,
-                        base_power: 5 + attack_multiplier,
-                        on_death: DeathCallback::Monster,
-                        xp:55
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                "boss" => {
-                    let hp_multiplier = ((MONSTER_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let attack_multiplier = ((MONSTER_ATTACK_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let defense_multiplier = ((MONSTER_DEFENSE_LEVEL_UP_BASE as i32 + level as i32 ) / MONSTER_LEVEL_UP_FACTOR) as i32;
-                    let mut boss = Object::new(x, y, 'W', "BOSS", colors::RED, true);
-                    boss.fighter = Some(Fighter{
-                        base_max_hp: 60 + hp_multiplier as i32,
-                        hp: 60 + hp_multiplier as i32,
-                        base_defense: 4 + defense_multiplier,
-                        base_power: 8 +attack_multiplier,
-                        on_death: DeathCallback::Monster,
-                        xp:110
-                    });
-                    boss.ai = Some(Ai::Basic);
-                    boss
-                }
-                _ => unreachable!(),
-            };
-
-            monster.alive= true;
-            objects.push(monster);
+        if game.inventory[inventory_id].charges == Some(0) {
+            let name = game.display_name(&game.inventory[inventory_id]);
+            game.log.add(format!("The {} is spent.", name), colors::RED);
+            return DidntTakeTurn;
         }
 
-    }
-
-    let max_items = from_dungeon_level(
-        &[
-            Transition { level: 1, value: 1 },
-            Transition { level: 4, value: 2 },
-        ],
-        level,
-    );
-
-    let num_items = rand::thread_rng().gen_range(0, max_items +1);
-
-    for _ in 0..num_items {
-        let x = rand::thread_rng().gen_range(room.x1 +1 , room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 +1 , room.y2);
-
-        let item_chances = [Item::Heal, Item::Lightning, Item::Sword, Item::Targe, Item::Chest];
-        let weights = [
-            35,
-            from_dungeon_level(
-                &[Transition {level: 4, value: 10,}],
-                level,
-            ),
-            from_dungeon_level(
-                &[Transition {level: 3,value: 5,}],
-                level,
-            ),
-            from_dungeon_level(
-                &[Transition {level: 6,value: 5,}],
-                level,
-            ),
-            from_dungeon_level(
-                &[Transition {level: 8,value: 5,}],
-                level,
-            ),
-        ];
-        let item_choice = WeightedIndex::new(&weights).unwrap();
-
-
-        if !is_blocked(x, y, map, objects){
-
-            let mut item = match item_chances[item_choice.sample(&mut rand::thread_rng())] {
-                Item::Heal => {
-                    let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    let mut object = Object::new(x, y, '#', "scroll of lightning bolt", colors::LIGHT_YELLOW, false, );
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::AttackBuff => {
-                    let mut object = Object::new(x, y, '+', "attack scroll", colors::VIOLET, false);
-                    object.item = Some(Item::AttackBuff);
-                    object
-                }
-                Item::Sword => {
-                    let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::RightHand, power_bonus: 3, defense_bonus: 0, max_hp_bonus: 0});
-                    object
-                }
-                Item::Chest => {
-                    let mut object = Object::new(x, y, '░', "chainmail armor", colors::COPPER, false);
-                    object.item = Some(Item::Chest);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::Chest, power_bonus: 0, defense_bonus: 2, max_hp_bonus: 10});
-                    object
-                }Item::Targe => {
-                    let mut object = Object::new(x, y, '◙', "targe", colors::DARK_HAN, false);
-                    object.item = Some(Item::Targe);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::LeftHand, power_bonus: 0, defense_bonus: 1, max_hp_bonus: 0});
-                    object
-                }
-            };
-            item.always_visible = true;
-            objects.push(item);
+        // taken now, the item may be used up before it is named
+        let appearance = game.display_name(&game.inventory[inventory_id]);
+        let name = game.inventory[inventory_id].name.clone();
+        let result = match item_use(item) {
+            Some(Lightning) => cast_lightning(tcod, inventory_id, object, game),
+            Some(Identify) => cast_identify(tcod, inventory_id, object, game),
+            Some(Recharge) => cast_recharge(tcod, inventory_id, object, game),
+            Some(Clairvoyance) => cast_clairvoyance(tcod, inventory_id, object, game),
+            Some(Shoot) => shoot_arrow(tcod, inventory_id, object, game),
+            _ => apply_item(item, inventory_id, object, game),
+        };
 
+        // backing out of the targeting leaves it a mystery
+        if !known && !matches!(result, UseResult::Cancelled) {
+            game.identify(item);
+            game.log.add(format!("The {} was a {}!", appearance, name), colors::LIGHT_VIOLET);
         }
-    }
-}
-
-fn use_item (tcod: &mut Tcod, inventory_id: usize, object: &mut [Object], game: &mut Game) -> PlayerAction{
-    use Item::*;
-    use PlayerAction::*;
 
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
-            Heal => cast_heal,
-            AttackBuff => cast_attack_buff,
-            Lightning => cast_lightning,
-            Sword => toggle_equipment,
-            Chest => toggle_equipment,
-            Targe => toggle_equipment,
-        };
-
-        match on_use(tcod, inventory_id, object, game){
+        match result {
             UseResult::UsedUp => {
-                &mut game.inventory.remove(inventory_id);
+                consume_item(inventory_id, game);
                 DidntTakeTurn
             }
             UseResult::UseAndTakeTurn => {
-                &mut game.inventory.remove(inventory_id);
+                consume_item(inventory_id, game);
                 TookTurn
             },
             UseResult::UseAndKept => {
                 DidntTakeTurn
             },
             UseResult::Cancelled => {
-                game.log.add("Cancelled", colors::WHITE);
+                game.log.add_categorized("Cancelled", MessageCategory::System);
                 DidntTakeTurn
             }
         }
     } else {
-        game.log.add(format!("The {} cannot be used.", game.inventory[inventory_id].name),colors::RED);
+        let name = game.display_name(&game.inventory[inventory_id]);
+        game.log.add(format!("The {} cannot be used.", name),colors::RED);
         DidntTakeTurn
     }
 }
 
-fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
-
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
-
-    let mut rooms = vec![];
-
-    for _ in 0..MAX_ROOMS {
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
-
-        let new_room = Rect::new(x, y, w, h);
-
-        let failed = rooms
-            .iter()
-            .any(|other_room|new_room.intersect_with(other_room));
-
-        if !failed {
-            create_room(new_room, &mut map);
-            place_object(new_room, &map, objects, level);
-            let (new_x, new_y) = new_room.center();
-            if rooms.is_empty() {
-                objects[PLAYER].set_pos(new_x, new_y);
-            }else{
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                if rand::random() {
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
-            }
-
-
-            rooms.push(new_room);
-        }
-    }
-
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = Object::new(
-        last_room_x,
-        last_room_y,
-        '<',
-        "Stairs",
-        colors::WHITE,
-        false,
-    );
-    stairs.always_visible = true;
-    objects.push(stairs);
-
-    map
-}
-
 fn render_all(
     tcod: &mut Tcod,
     objects: &[Object],
@@ -1163,16 +899,34 @@ fn render_all(
         tcod.fov.compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
     }
 
-    for y in 0..MAP_HEIGHT{
-        for x in 0..MAP_WIDTH{
+    let palette = &game.palette.themed(game.theme);
+
+    let lit_room = lit_room_around(&objects[PLAYER], game);
+    let fov = &tcod.fov;
+    let revealed = &tcod.revealed;
+    let is_visible = |x: i32, y: i32| is_tile_visible(x, y, fov, lit_room) || revealed.contains(&(x, y));
+
+    let (map_width, map_height) = map_size(&game.map);
+    let (view_width, view_height) = (tcod.con.width(), tcod.con.height());
+    tcod.camera = camera_origin(objects[PLAYER].pos(), (map_width, map_height), (view_width, view_height));
+    let camera = tcod.camera;
+    let in_view = |x: i32, y: i32| {
+        let (x, y) = (x - camera.0, y - camera.1);
+        x >= 0 && y >= 0 && x < view_width && y < view_height
+    };
+
+    for y in 0..map_height{
+        for x in 0..map_width{
 
-            let visible= tcod.fov.is_in_fov(x, y);
-            let wall = game.map[x as usize][y as usize].block_sight;
+            let visible= is_visible(x, y);
+            let tile = game.map[x as usize][y as usize];
+            let wall = tile.blocked && tile.block_sight;
             let color = match (visible, wall){
-                (false, true) => COLOR_DARK_WALL,
-                (false, false) => COLOR_DARK_GROUND,
-                (true, false) => COLOR_LIGHT_GROUND,
-                (true, true) => COLOR_LIGHT_WALL
+                _ if tile.chasm => colors::BLACK,
+                (false, true) => palette.dark_wall,
+                (false, false) => palette.dark_ground,
+                (true, false) => palette.light_ground,
+                (true, true) => palette.light_wall
             };
 
             let explored = &mut game.map[x as usize][y as usize].explored;
@@ -1181,32 +935,94 @@ fn render_all(
                 *explored = true;
             }
 
-            if *explored {
-                tcod.con.set_char_background(x, y, color, BackgroundFlag::Set);
+            if *explored && in_view(x, y) {
+                tcod.con.set_char_background(x - camera.0, y - camera.1, color, BackgroundFlag::Set);
+                // thickets and pools sit on the floor, one only blocking sight and the other only movement
+                let feature = match (tile.blocked, tile.block_sight) {
+                    (false, true) => Some(('"', if visible { colors::DARK_GREEN } else { colors::DARKEST_GREEN })),
+                    (true, false) => Some(('~', if visible { colors::LIGHT_BLUE } else { colors::DARK_BLUE })),
+                    _ => None,
+                };
+                if let Some((glyph, glyph_color)) = feature {
+                    tcod.con.set_default_foreground(glyph_color);
+                    tcod.con.put_char(x - camera.0, y - camera.1, glyph, BackgroundFlag::None);
+                }
+                if game.map[x as usize][y as usize].trap.is_some_and(|trap| trap.found) {
+                    tcod.con.set_default_foreground(colors::RED);
+                    tcod.con.put_char(x - camera.0, y - camera.1, '^', BackgroundFlag::None);
+                }
+            }
+        }
+    }
+
+    let mut to_draw: Vec<_> = objects
+        .iter()
+        .filter(|o| {
+            o.cells().into_iter().any(|(x, y)| is_visible(x, y))
+                || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
+        })
+        .collect();
+    to_draw.sort_by_key(|o| o.draw_layer());
+
+    if tcod.config.remember_monsters {
+        // dimmed ghosts of monsters spotted earlier, drawn first so anything in view covers them
+        for object in objects.iter().filter(|o| !is_visible(o.x, o.y)) {
+            if let Some((x, y)) = object.last_seen {
+                if !is_visible(x, y) && game.map[x as usize][y as usize].explored && in_view(x, y) {
+                    let color = colors::lerp(palette.color_of(object), palette.dark_ground, 0.6);
+                    tcod.con.set_default_foreground(color);
+                    tcod.con.put_char(x - camera.0, y - camera.1, object.char, BackgroundFlag::None);
+                }
+            }
+        }
+    }
+
+    for object in &to_draw {
+        match (tcod.tween, object.previous_pos) {
+            // fade the glyph out of the old tile and into the new one
+            (Some(progress), Some((old_x, old_y))) if (old_x, old_y) != object.pos() && is_visible(old_x, old_y) => {
+                let color = palette.color_of(object);
+                for &((x, y), fade) in &[((old_x, old_y), progress), (object.pos(), 1.0 - progress)] {
+                    if !in_view(x, y) {
+                        continue;
+                    }
+                    let (x, y) = (x - camera.0, y - camera.1);
+                    let background = tcod.con.get_char_background(x, y);
+                    tcod.con.set_default_foreground(colors::lerp(color, background, fade));
+                    tcod.con.put_char(x, y, object.char, BackgroundFlag::None);
+                }
             }
+            _ => object.draw(&mut tcod.con, palette, camera),
         }
     }
 
-    let mut to_draw: Vec<_> = objects
-        .iter()
-        .filter(|o| {
-            tcod.fov.is_in_fov(o.x, o.y)
-                || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
-        })
-        .collect();
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
-
-    for object in &to_draw {
-        object.draw(&mut tcod.con);
+    let label_frames = tcod.settings.animation_frames(DAMAGE_LABEL_FRAMES) as u32;
+    let label_rise = tcod.settings.animation_frames(DAMAGE_LABEL_RISE) as u32;
+    for label in &game.damage_labels {
+        let (x, y) = (label.x, label.y - (label.age / label_rise) as i32);
+        if !is_visible(label.x, label.y) || !in_view(x, y) {
+            continue;
+        }
+        let (x, y) = (x - camera.0, y - camera.1);
+        let fade = label.age as f32 / label_frames as f32;
+        let background = tcod.con.get_char_background(x, y);
+        tcod.con.set_default_foreground(colors::lerp(colors::LIGHT_RED, background, fade));
+        tcod.con.print_ex(x, y, BackgroundFlag::None, TextAlignment::Left, label.amount.to_string());
+    }
+    for label in &mut game.damage_labels {
+        label.age += 1;
     }
+    game.damage_labels.retain(|label| label.age < label_frames);
 
     if let Some(_fighter) = objects[PLAYER].fighter {
         tcod.panel.set_default_background(colors::BLACK);
         tcod.panel.clear();
 
         let mut y = MSG_HEIGHT as i32;
-        for &(ref msg, color) in game.log.iter().rev() {
-            let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        for entry in game.log.iter().rev() {
+            let msg = if game.log_turns { entry.with_turn() } else { entry.text.clone() };
+            let color = entry.color_in(game.palette.palette());
+            let msg_height = tcod.panel.get_height_rect(MSG_X, y, tcod.config.msg_width(), 0, &msg);
             y -= msg_height;
 
             if y < 0 {
@@ -1214,14 +1030,14 @@ fn render_all(
             }
 
             tcod.panel.set_default_foreground(color);
-            tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+            tcod.panel.print_rect(MSG_X, y, tcod.config.msg_width(), 0, &msg);
         }
 
 
         let hp = objects[PLAYER].fighter.map_or(0,|f |f.hp);
-        let max_hp = objects[PLAYER].fighter.map_or(0,|f |f.base_max_hp);
-        let attack = objects[PLAYER].fighter.map_or(0,|f |f.base_power);
-        let defense = objects[PLAYER].fighter.map_or(0,|f |f.base_defense);
+        let max_hp = objects[PLAYER].max_hp(game);
+        let base_attack = objects[PLAYER].fighter.map_or(0,|f |f.base_power);
+        let base_defense = objects[PLAYER].fighter.map_or(0,|f |f.base_defense);
 
         render_bar(&mut tcod.panel, 1, 1, BAR_WIDTH, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
 
@@ -1230,16 +1046,22 @@ fn render_all(
             5,
             BackgroundFlag::None,
             TextAlignment::Left,
-            format!("Dungeon level: {}", game.dungeon_level),
+            format!("Dungeon level: {} ({})", game.dungeon_level, game.theme.theme().name),
         );
 
+        // the cursor over the panel has nothing on the map beneath it
+        let info = if (tcod.mouse.cy as i32) < view_height {
+            get_info_under_mouse(tcod.mouse_tile(), objects, &tcod.fov, game)
+        } else {
+            String::new()
+        };
         tcod.panel.set_default_foreground(colors::LIGHT_GREY);
         tcod.panel.print_ex(
             1,
             0,
             BackgroundFlag::None,
             TextAlignment::Left,
-            get_names_under_mouse(tcod.mouse, objects, &tcod.fov)
+            info,
         );
 
         tcod.panel.set_default_foreground(colors::LIGHT_AZURE);
@@ -1248,25 +1070,72 @@ fn render_all(
             3,
             BackgroundFlag::None,
             TextAlignment::Left,
-            format!("Attack: {}", attack)
+            stat_line("Attack", base_attack, objects[PLAYER].power(game))
         );
 
+        // tucked in at the end of the attack line, clear of the messages
+        if let Some(direction) = stairs_direction(objects, game).filter(|_| game.stairs_compass) {
+            tcod.panel.set_default_foreground(colors::WHITE);
+            tcod.panel.print_ex(BAR_WIDTH, 3, BackgroundFlag::None, TextAlignment::Right, format!("<{}", direction));
+        }
+
         tcod.panel.set_default_foreground(colors::LIGHT_AZURE);
         tcod.panel.print_ex(
             1,
             4,
             BackgroundFlag::None,
             TextAlignment::Left,
-            format!("Defense: {}", defense)
+            stat_line("Defense", base_defense, objects[PLAYER].defense(game))
         );
 
+        let mut effects: Vec<String> = objects[PLAYER]
+            .effects
+            .iter()
+            .map(|e| format!("{} {}", e.kind.abbreviation(), e.turns))
+            .collect();
+        if game.sneaking {
+            effects.push("Sneak".into());
+        }
+        let max_mana = objects[PLAYER].fighter.map_or(0, |f| f.max_mana);
+        let mut effects_x = 1;
+        if max_mana > 0 {
+            let mana = format!("MP {}/{}", objects[PLAYER].fighter.map_or(0, |f| f.mana), max_mana);
+            tcod.panel.set_default_foreground(colors::LIGHT_BLUE);
+            tcod.panel.print_ex(1, 2, BackgroundFlag::None, TextAlignment::Left, &mana);
+            effects_x += mana.len() as i32 + 1;
+        }
+        tcod.panel.set_default_foreground(colors::LIGHT_GREEN);
+        tcod.panel.print_ex(
+            effects_x,
+            2,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            effects.join(" "),
+        );
+
+        let (hunger, hunger_color) = hunger_status(game.nutrition);
+        tcod.panel.set_default_foreground(hunger_color);
+        tcod.panel.print_ex(
+            1,
+            6,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            hunger,
+        );
+        if game.difficulty.encumbrance() {
+            let load = format!("Wt {}/{}", carried_weight(game), carry_capacity(&objects[PLAYER]));
+            let load_color = if is_burdened(objects, game) { colors::ORANGE } else { colors::LIGHT_GREY };
+            tcod.panel.set_default_foreground(load_color);
+            tcod.panel.print_ex(hunger.len() as i32 + 2, 6, BackgroundFlag::None, TextAlignment::Left, load);
+        }
+
 
         blit(
-            &mut tcod.panel,
+            &tcod.panel,
             (0, 0),
-            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            (tcod.config.screen_width, PANEL_HEIGHT),
             &mut tcod.root,
-            (0, PANEL_Y),
+            (0, tcod.config.panel_y()),
             1.0,
             1.0,
         )
@@ -1274,9 +1143,9 @@ fn render_all(
 
 
     blit(
-        &mut tcod.con,
+        &tcod.con,
         (0, 0),
-        (MAP_WIDTH, MAP_HEIGHT),
+        (view_width, view_height),
         &mut tcod.root,
         (0, 0),
         1.0,
@@ -1285,19 +1154,173 @@ fn render_all(
 
 }
 
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
-    let (x, y) = (mouse.cx as i32, mouse.cy as i32);
+// the effective value, with the equipment's share spelled out
+fn stat_line(name: &str, base: i32, effective: i32) -> String {
+    match effective - base {
+        0 => format!("{}: {}", name, effective),
+        bonus => format!("{}: {} ({:+})", name, effective, bonus),
+    }
+}
+
+fn get_info_under_mouse((x, y): (i32, i32), objects: &[Object], fov_map: &impl Visibility, game: &Game) -> String {
+    let under_mouse = objects
+        .iter()
+        .filter(|obj |{obj.occupies(x, y) && fov_map.is_in_fov(x, y)})
+        .collect::<Vec<_>>();
+
+    // a single object gets a closer look
+    if let [object] = under_mouse[..] {
+        if object.equipment.is_some() {
+            return format!("{} ({})", object.name, equipment_comparison(object, game));
+        }
+        if let (Some(fighter), true) = (object.fighter, object.ai.is_some_and(|ai| ai.is_hostile())) {
+            return format!(
+                "{}: {}/{} hp, {}",
+                object.name, fighter.hp, object.max_hp(game), object.threat_to(&objects[PLAYER], game)
+            );
+        }
+        if let Some(item) = object.item {
+            let description = if game.is_identified(item) || object.identified {
+                item.description()
+            } else {
+                "unknown effect"
+            };
+            return format!("{}: {}", game.display_name(object), description);
+        }
+    }
 
-    let names = objects
+    let mut names = under_mouse
         .iter()
-        .filter(|obj |{obj.pos() == (x,y) && fov_map.is_in_fov(obj.x, obj.y)})
-        .map(|obj |obj.name.clone())
+        .map(|obj |game.display_name(obj))
         .collect::<Vec<_>>();
+    let (map_width, map_height) = map_size(&game.map);
+    if x >= 0 && y >= 0 && x < map_width && y < map_height {
+        let tile = &game.map[x as usize][y as usize];
+        match tile.trap {
+            Some(trap) if trap.found && tile.explored => names.push(trap.kind.to_string()),
+            _ => {}
+        }
+        if tile.chasm && tile.explored {
+            names.push("chasm".into());
+        }
+    }
+
+    names.join(", ")
+}
+
+
+
+// every binding handle_keys knows about; the help screen is built from this, so keep them in step
+const CONTROLS: &[(&str, &str)] = &[
+    ("Arrow keys", "move, or attack what's in the way"),
+    ("Left click", "walk to an explored spot"),
+    ("f", "pick up an item"),
+    ("e", "pick up and equip an item"),
+    ("i", "use an item from the inventory"),
+    ("d", "drop an item"),
+    ("A", "drop everything you're not wearing"),
+    ("x", "examine an item in the inventory"),
+    ("t", "shoot an arrow"),
+    ("T", "throw your weapon"),
+    ("s", "search for hidden traps"),
+    ("D", "disarm a known trap next to you"),
+    ("S", "toggle sneaking, slower but quieter"),
+    ("z", "cast a spell"),
+    ("1-9", "cast a known spell directly"),
+    ("Tab", "character information"),
+    ("l", "message history"),
+    ("M", "minimap"),
+    ("P", "write the level out to map.txt (--debug only)"),
+    ("Space", "take the stairs down"),
+    ("o", "settings"),
+    ("?", "this help"),
+    ("Alt+Enter", "toggle fullscreen"),
+    ("Escape", "pause menu"),
+];
+
+// unidentified items keep their secrets until drunk, read or identified
+fn examine_item(item: &Object, game: &Game, root: &mut Root) {
+    let mut text = game.display_name(item);
+    if let Some(kind) = item.item {
+        if game.is_identified(kind) || item.identified {
+            text += &format!("\n\n{}", item_lore(kind));
+        } else {
+            text += "\n\nYou have no idea what this does yet.";
+        }
+    }
+    if item.equipment.is_some() {
+        text += &format!("\n\n{}", equipment_comparison(item, game));
+    }
+    msgbox(&format!("{}\n\nPress any key to continue.", text), HELP_SCREEN_WIDTH, root);
+}
 
-    return names.join(", ");
+fn help_screen(root: &mut Root) {
+    let lines: Vec<String> = CONTROLS
+        .iter()
+        .map(|(key, action)| format!("{:>10}  {}", key, action))
+        .collect();
+    let text = format!("Controls\n\n{}\n\nPress any key to continue.", lines.join("\n"));
+    msgbox(&text, HELP_SCREEN_WIDTH, root);
 }
 
+// a shrunken view of the explored level, with the player and any stairs found so far
+fn show_minimap(objects: &[Object], game: &Game, root: &mut Root) {
+    let palette = &game.palette.themed(game.theme);
+    let (map_width, map_height) = map_size(&game.map);
+    let width = (map_width + MINIMAP_SCALE - 1) / MINIMAP_SCALE;
+    let height = (map_height + MINIMAP_SCALE - 1) / MINIMAP_SCALE;
+
+    let mut minimap = Offscreen::new(width, height);
+    minimap.set_default_background(colors::BLACK);
+    minimap.clear();
+
+    for cell_y in 0..height {
+        for cell_x in 0..width {
+            let tiles: Vec<&Tile> = (0..MINIMAP_SCALE)
+                .flat_map(|dx| (0..MINIMAP_SCALE).map(move |dy| (cell_x * MINIMAP_SCALE + dx, cell_y * MINIMAP_SCALE + dy)))
+                .filter(|&(x, y)| x < map_width && y < map_height)
+                .map(|(x, y)| &game.map[x as usize][y as usize])
+                .filter(|tile| tile.explored)
+                .collect();
+
+            // any open ground in the cell wins, so corridors don't vanish between walls
+            let color = if tiles.iter().any(|tile| !tile.blocked) {
+                palette.light_ground
+            } else if !tiles.is_empty() {
+                palette.dark_wall
+            } else {
+                continue;
+            };
+            minimap.set_char_background(cell_x, cell_y, color, BackgroundFlag::Set);
+        }
+    }
+
+    let mut mark = |(x, y): (i32, i32), char: char, color: Color| {
+        minimap.set_default_foreground(color);
+        minimap.put_char(x / MINIMAP_SCALE, y / MINIMAP_SCALE, char, BackgroundFlag::None);
+    };
+    if let Some((x, y)) = stairs_position(objects) {
+        if game.map[x as usize][y as usize].explored {
+            mark((x, y), '<', colors::WHITE);
+        }
+    }
+    mark(objects[PLAYER].pos(), '@', colors::WHITE);
+
+    let x = root.width() / 2 - width / 2;
+    let y = root.height() / 2 - height / 2;
+    blit(&minimap, (0, 0), (width, height), root, (x, y), 1.0, 1.0);
+    root.flush();
+    wait_for_keypress(root);
+}
 
+// the latest messages that fit on screen, each with the turn it happened on
+fn message_history(game: &Game, tcod: &mut Tcod) {
+    let shown = (tcod.config.screen_height - 6) as usize;
+    let skipped = game.log.len().saturating_sub(shown);
+    let lines: Vec<String> = game.log.iter().skip(skipped).map(|entry| entry.with_turn()).collect();
+    let text = format!("Message history\n\n{}", lines.join("\n"));
+    msgbox(&text, tcod.config.screen_width - 10, &mut tcod.root);
+}
 
 fn handle_keys(key: Key, tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) -> PlayerAction {
     use tcod::input::KeyCode::*;
@@ -1317,42 +1340,65 @@ fn handle_keys(key: Key, tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut
         ) => {
             let fullscreen: bool = tcod.root.is_fullscreen();
             tcod.root.set_fullscreen(!fullscreen);
+            tcod.settings.fullscreen = !fullscreen;
+            if let Err(e) = save_settings(&tcod.settings) {
+                game.log.add_categorized(format!("Could not save the settings: {}", e), MessageCategory::System);
+            }
             DidntTakeTurn
         }
 
-        (Key {code: Escape, ..}, _, )=> Exit,
+        (Key {code: Escape, ..}, _, )=> pause_menu(game, tcod),
 
         (Key {code: Up,..}, true) => {
-            player_move_or_attack(0, -1, objects, game);
+            player_move_or_attack(0, -1, tcod.settings.auto_pickup, objects, game);
             TookTurn
         },
         (Key {code: Down,..}, true) => {
-            player_move_or_attack(0, 1, objects, game);
+            player_move_or_attack(0, 1, tcod.settings.auto_pickup, objects, game);
             TookTurn
         },
         (Key {code: Left,..}, true) => {
-            player_move_or_attack(-1, 0, objects, game);
+            player_move_or_attack(-1, 0, tcod.settings.auto_pickup, objects, game);
             TookTurn
         },
         (Key {code: Right,..}, true) => {
-            player_move_or_attack(1, 0, objects, game);
+            player_move_or_attack(1, 0, tcod.settings.auto_pickup, objects, game);
             TookTurn
         },
         (Key {printable: 'f',..}, true) => {
+            let item_ids = items_at(objects[PLAYER].pos(), objects);
+            let chosen = match item_ids.len() {
+                0 => vec![],
+                1 => item_ids,
+                _ => pickup_menu(&item_ids, objects, game, &mut tcod.root),
+            };
+
+            // highest index first, so swap_remove doesn't move the ones still to pick
+            for item_id in chosen.into_iter().rev() {
+                pick_item_up(item_id, objects, game);
+            }
+            DidntTakeTurn
+        },
+        (Key { printable: 'e', .. }, true) => {
             let item_id = objects
                 .iter()
-                .position(|object |object.pos() == objects[PLAYER].pos() && object.item.is_some());
+                .position(|object |object.pos() == objects[PLAYER].pos() && object.equipment.is_some());
 
             if let Some(item_id) = item_id {
+                let inventory_len = game.inventory.len();
                 pick_item_up(item_id, objects, game);
+                if game.inventory.len() > inventory_len {
+                    toggle_equipment(inventory_len, objects, game);
+                }
             }
             DidntTakeTurn
         },
         (Key { printable: 'i', .. }, true) => {
             let inventory_index = inventory_menu(
-                &game.inventory,
+                game,
+                InventoryMenu::Use,
                 "Press the key next to an item to use it, or any other to cancel.\n",
-                &mut tcod.root);
+                tcod);
 
             if let Some(inventory_index) = inventory_index {
                 return use_item(tcod, inventory_index, objects, game);
@@ -1360,11 +1406,12 @@ fn handle_keys(key: Key, tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut
             DidntTakeTurn
         },
         (Key { code: Spacebar, .. }, true) => {
-            let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "stairs");
-            if player_on_stairs {
-                next_level(tcod, objects, game);
+            if stairs_position(objects) == Some(objects[PLAYER].pos()) {
+                if stairs_sealed(objects) {
+                    game.log.add_categorized("The stairs are sealed while the guardian of this level lives.", MessageCategory::Danger);
+                } else {
+                    next_level(tcod, objects, game);
+                }
             }
             DidntTakeTurn
         },
@@ -1377,28 +1424,127 @@ fn handle_keys(key: Key, tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut
                 let msg = format!(
                     "Character information
 
+Class: {}
 Level: {}
 Experience: {}
 Experience to level up: {}
+Turns: {}
 
 Maximum HP: {}
 Attack: {}
-Defense: {}",
-                    level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game)
+Defense: {}
+
+Equipment:
+{}",
+                    game.class, level, fighter.xp, level_up_xp, game.turns, player.max_hp(game), player.power(game), player.defense(game),
+                    gear_lines(game)
                 );
                 msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
             }
 
             DidntTakeTurn
         }
+        (Key { printable: 't', .. }, true) => {
+            let arrows = game.inventory.iter().position(|item| item.item == Some(Item::Arrow));
+            match arrows {
+                Some(arrows) => return use_item(tcod, arrows, objects, game),
+                None => game.log.add_categorized("You have no arrows.", MessageCategory::Item),
+            }
+            DidntTakeTurn
+        }
+        (Key { printable: 'T', .. }, true) => throw_equipped_weapon(tcod, objects, game),
+        (Key { printable: 's', .. }, true) => {
+            search_for_traps(objects, game);
+            TookTurn
+        }
+        (Key { printable: 'D', .. }, true) => {
+            if disarm_trap(objects, game) {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        (Key { printable: 'S', .. }, true) => {
+            game.sneaking = !game.sneaking;
+            if game.sneaking {
+                game.log.add_categorized("You start moving quietly.", MessageCategory::System);
+            } else {
+                game.log.add_categorized("You stop sneaking.", MessageCategory::System);
+            }
+            DidntTakeTurn
+        }
+        (Key { printable: 'z', .. }, true) => {
+            match spell_menu(game, &mut tcod.root) {
+                Some(spell) => cast_spell(tcod, spell, objects, game),
+                None => DidntTakeTurn,
+            }
+        }
+        (Key { printable: digit @ '1'..='9', .. }, true) => {
+            let index = digit.to_digit(10).unwrap_or(1) as usize - 1;
+            match game.spells.get(index) {
+                Some(&spell) => cast_spell(tcod, spell, objects, game),
+                None => DidntTakeTurn,
+            }
+        }
+        (Key { printable: 'o', .. }, _) => {
+            settings_menu(game, tcod);
+            DidntTakeTurn
+        }
+        (Key { printable: 'M', .. }, _) => {
+            show_minimap(objects, game, &mut tcod.root);
+            DidntTakeTurn
+        }
+        // the dump shows the whole level, so it is only for debugging the generators
+        (Key { printable: 'P', .. }, _) if tcod.debug => {
+            match dump_map(game, objects, MAP_DUMP_FILE) {
+                Ok(()) => game.log.add_categorized(format!("Level written to {}.", MAP_DUMP_FILE), MessageCategory::System),
+                Err(e) => game.log.add_categorized(format!("Couldn't write the level: {}", e), MessageCategory::System),
+            }
+            DidntTakeTurn
+        }
+        (Key { printable: 'l', .. }, _) => {
+            message_history(game, tcod);
+            DidntTakeTurn
+        }
+        (Key { printable: '?', .. }, _) => {
+            help_screen(&mut tcod.root);
+            DidntTakeTurn
+        }
+        (Key { printable: 'x', .. }, _) => {
+            let inventory_index = inventory_menu(
+                game,
+                InventoryMenu::Examine,
+                "Press the key next to an item to examine it, or any other to cancel.\n",
+                tcod,
+            );
+            if let Some(inventory_index) = inventory_index {
+                examine_item(&game.inventory[inventory_index], game, &mut tcod.root);
+            }
+            DidntTakeTurn
+        }
+        (Key { printable: 'A', .. }, true) => {
+            let unequipped = game.inventory.iter().filter(|item| !item.equipment.is_some_and(|e| e.equipped)).count();
+            if unequipped == 0 {
+                game.log.add_categorized("You have nothing to drop.", MessageCategory::Item);
+            } else if confirm(&format!("Drop all {} unequipped items?", unequipped), &mut tcod.root) {
+                drop_all(objects, game);
+            }
+            DidntTakeTurn
+        }
         (Key { printable: 'd', .. }, true) => {
             let inventory_index = inventory_menu(
-                &game.inventory,
-                "Press the key next to an item to drop it, or any other to cancel.\n'",
-                &mut tcod.root,
+                game,
+                InventoryMenu::Drop,
+                "Press the key next to an item to drop it, or any other to cancel.\n",
+                tcod,
             );
             if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, objects, game);
+                let item = &game.inventory[inventory_index];
+                let equipped = item.equipment.is_some_and(|e| e.equipped);
+                // a full tile turns the drop down anyway, so there is nothing to take off for
+                if !equipped || item_underfoot(objects) || confirm(&format!("Take off and drop the {}?", item.name), &mut tcod.root) {
+                    drop_item(inventory_index, objects, game);
+                }
             }
             DidntTakeTurn
         }
@@ -1407,124 +1553,158 @@ Defense: {}",
     }
 }
 
-fn player_move_or_attack(dx: i32, dy: i32, objects: &mut [Object], game: &mut Game){
-    let x = objects[PLAYER].x + dx;
-    let y = objects[PLAYER].y + dy;
+fn hostile_in_view<'a>(objects: &'a [Object], fov: &FovMap) -> Option<&'a Object> {
+    objects
+        .iter()
+        .find(|o| o.ai.is_some_and(|ai| ai.is_hostile()) && fov.is_in_fov(o.x, o.y))
+}
 
-    let target_id = objects.iter().position(|object |object.fighter.is_some() && object.pos() == (x, y));
+fn handle_mouse_click(tcod: &mut Tcod, objects: &[Object], game: &mut Game) {
+    let goal = tcod.mouse_tile();
+    let (map_width, map_height) = map_size(&game.map);
+    let on_panel = tcod.mouse.cy as i32 >= tcod.con.height();
+    if on_panel || goal.0 < 0 || goal.1 < 0 || goal.0 >= map_width || goal.1 >= map_height || !objects[PLAYER].alive {
+        return;
+    }
 
-    match target_id {
-        Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
-            player.attack(target, game);
-        }
-        None => {
+    if let Some(monster) = hostile_in_view(objects, &tcod.fov) {
+        game.log.add_categorized(format!("Not with the {} in sight.", monster.name), MessageCategory::Danger);
+    } else if !game.map[goal.0 as usize][goal.1 as usize].explored
+        || travel_step(&game.map, objects[PLAYER].pos(), goal).is_none()
+    {
+        game.log.add_categorized("You don't know a way there.", MessageCategory::Danger);
+    } else {
+        tcod.travel = Some(goal);
+    }
+}
+
+// one step of a click-to-move walk, which stops as soon as anything hostile shows up
+fn travel_towards(goal: (i32, i32), tcod: &mut Tcod, objects: &mut [Object], game: &mut Game) -> PlayerAction {
+    if let Some(monster) = hostile_in_view(objects, &tcod.fov) {
+        game.log.add_categorized(format!("You spot a {} and stop.", monster.name), MessageCategory::Danger);
+        tcod.travel = None;
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    let (x, y) = objects[PLAYER].pos();
+    match travel_step(&game.map, (x, y), goal) {
+        Some((dx, dy)) if (dx, dy) != (0, 0) && !is_blocked(x + dx, y + dy, &game.map, objects) => {
             move_by(PLAYER, dx, dy, &game.map, objects);
+            if objects[PLAYER].pos() == goal {
+                tcod.travel = None;
+            }
+            PlayerAction::TookTurn
+        }
+        _ => {
+            tcod.travel = None;
+            PlayerAction::DidntTakeTurn
         }
     }
 }
 
 fn next_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
-    game.log.add(
-        "You take a moment to rest.",
-        colors::VIOLET,
-    );
-    let heal_hp = objects[PLAYER].max_hp(game) / 2;
-    objects[PLAYER].cast(tcod, "heal", heal_hp, game);
+    descend(objects, game, &tcod.config);
+    arrive_on_new_level(tcod, objects, game);
+}
 
-    game.log.add(
-        "After a rare moment of peace, you going further in the dungeon.. As always",
-        colors::RED,
-    );
-    game.dungeon_level += 1;
-    game.map = make_map(objects, game.dungeon_level);
+fn fall_to_next_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
+    fall_into_chasm(objects, game, &tcod.config);
+    arrive_on_new_level(tcod, objects, game);
+}
+
+fn arrive_on_new_level(tcod: &mut Tcod, objects: &mut [Object], game: &mut Game) {
     initialise_fov(&game.map, tcod);
+
+    // checkpoint every descent, but a failed save shouldn't end the run
+    if let Err(e) = save_game(objects, game, SAVE_SLOT) {
+        game.log.add_categorized(format!("Could not autosave: {}", e), MessageCategory::System);
+    }
 }
 
 fn initialise_fov(map: &Map, tcod: &mut Tcod) {
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(
-                x,
-                y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked,
-            );
-        }
+    // a saved map may not match the configured size
+    let (map_width, map_height) = map_size(map);
+    if tcod.fov.size() != (map_width, map_height) {
+        tcod.fov = FovMap::new(map_width, map_height);
     }
-    tcod.con.clear();
-}
 
-fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<dyn Error>> {
-    let save_data = serde_json::to_string(&(objects, game))?;
-    let mut file = File::create("Savegame")?;
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
+    fill_fov(map, &mut tcod.fov);
+    tcod.con.clear();
 }
 
-fn load_game() -> Result<(Vec<Object>, Game), Box<dyn Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open("Savegame")?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Vec<Object>, Game)>(&json_save_state)?;
-    Ok(result)
-}
+fn hall_of_records(root: &mut Root) {
+    let mut scores = load_scores().unwrap_or_default();
+    if scores.is_empty() {
+        msgbox("\nNo runs recorded yet.\n", 24, root);
+        return;
+    }
 
-fn new_game(tcod: &mut Tcod) -> (Vec<Object>, Game) {
-    let mut player: Object = Object::new(0,0,'@', "player", colors::WHITE, true);
-    player.fighter = Some(Fighter {
-        base_max_hp: 100,
-        hp: 100,
-        base_defense: 1,
-        base_power: 4,
-        on_death: DeathCallback::Player,
-        xp:0
+    scores.sort_by(|a, b| {
+        b.dungeon_level
+            .cmp(&a.dungeon_level)
+            .then(b.player_level.cmp(&a.player_level))
     });
-    player.alive= true;
 
-    let mut objects = vec![player];
-
-    let mut game = Game {
-        map: make_map(&mut objects, 1),
-        log: vec![],
-        inventory: vec![],
-        dungeon_level: 1
-    };
+    let lines: Vec<String> = scores
+        .iter()
+        .take(RECORDS_SHOWN)
+        .enumerate()
+        .map(|(rank, score)| {
+            format!(
+                "{:>2}. depth {:>2}, level {:>2}, {} xp, {} turns, {}",
+                rank + 1, score.dungeon_level, score.player_level, score.xp, score.turns, score.cause
+            )
+        })
+        .collect();
+    msgbox(&format!("Hall of records\n\n{}", lines.join("\n")), RECORDS_SCREEN_WIDTH, root);
+}
 
-    let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 3
-    });
-    game.inventory.push(dagger);
+fn new_game(tcod: &mut Tcod) -> Option<(Vec<Object>, Game)> {
+    let choices: Vec<String> = Difficulty::ALL.iter().map(|d| d.to_string()).collect();
+    let difficulty = menu("Choose a difficulty:\n", &choices, 24, &mut tcod.root)?;
+    let choices: Vec<String> = GameMode::ALL.iter().map(|m| m.to_string()).collect();
+    let mode = menu("Choose a mode:\n", &choices, 40, &mut tcod.root)?;
+    let choices: Vec<String> = PlayerClass::ALL.iter().map(|c| format!("{}: {}", c, c.description())).collect();
+    let class = menu("Choose a class:\n", &choices, 44, &mut tcod.root)?;
+    let (mut objects, mut game) = new_world(&tcod.config, Difficulty::ALL[difficulty]);
+    game.mode = GameMode::ALL[mode];
+    PlayerClass::ALL[class].outfit(&mut objects[PLAYER], &mut game);
+    // picks remembered from the last run point at a different pack
+    tcod.inventory_cursors.clear();
 
     initialise_fov(&game.map, tcod);
 
     game.log.add("Welcome stranger, brace yourself, you're alone now..",colors::RED);
 
-    (objects, game)
+    Some((objects, game))
 }
 
 fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
 
     let mut previous_player_position = (-1, -1);
-    let mut key = Default::default();
 
     while !tcod.root.window_closed(){
         tcod.con.clear();
 
-        match input::check_for_event(input::MOUSE | input::KEY_PRESS){
-            Some ((_, Event::Mouse(m))) => tcod.mouse = m,
-            Some ((_, Event::Key(k))) => key = k,
-            _ => key = Default::default(),
-        }
+        let key = match check_for_event() {
+            Some(Event::Mouse(m)) => {
+                tcod.mouse = m;
+                if m.lbutton_pressed {
+                    handle_mouse_click(tcod, objects, game);
+                }
+                Default::default()
+            }
+            Some(Event::Key(k)) => {
+                // any key takes back control from a click-to-move walk
+                tcod.travel = None;
+                k
+            }
+            _ => Default::default(),
+        };
 
         let fov_recompute = previous_player_position != (objects[PLAYER].pos());
-        render_all(tcod, &objects, game, fov_recompute);
+        render_all(tcod, objects, game, fov_recompute);
+        remember_monsters(objects, game, &tcod.fov);
 
         tcod.root.flush();
 
@@ -1533,76 +1713,335 @@ fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
         let player: &mut Object = &mut objects[PLAYER];
         previous_player_position = player.pos();
 
-        let player_action = handle_keys(key, tcod, objects, game);
+        let mut player_action = handle_keys(key, tcod, objects, game);
+        if let Some(goal) = tcod.travel {
+            player_action = travel_towards(goal, tcod, objects, game);
+        }
+        objects.append(&mut game.pending_objects);
 
         if player_action == PlayerAction::Exit {
-            save_game(objects, game).unwrap();
+            if let Err(e) = save_game(objects, game, SAVE_SLOT) {
+                msgbox(&format!("\nCould not save: {}\n", e), 24, &mut tcod.root);
+            }
             break
         }
 
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, game, objects, &tcod.fov);
-                }
+        if player_action == PlayerAction::Abandon {
+            // a dead player's score went in when they died
+            if objects[PLAYER].alive {
+                let entry = ScoreEntry::new(&objects[PLAYER], game, "quit".to_string());
+                let _ = record_score(entry);
+            }
+            if let Err(e) = delete_save(SAVE_SLOT) {
+                msgbox(&format!("\nCould not delete the save: {}\n", e), 24, &mut tcod.root);
+            }
+            break
+        }
+
+        if player_action == PlayerAction::TookTurn {
+            if objects[PLAYER].alive && objects[PLAYER].over_chasm(&game.map) {
+                fall_to_next_level(tcod, objects, game);
+            }
+            end_player_turn(objects, game, &tcod.fov);
+            if game.animate_moves {
+                animate_monster_moves(tcod, objects, game);
+            }
+        }
+
+        if game.won {
+            tcod.con.clear();
+            render_all(tcod, objects, game, false);
+            tcod.root.flush();
+            victory_screen(&objects[PLAYER], game, &mut tcod.root);
+            let entry = ScoreEntry::new(&objects[PLAYER], game, "won the game".to_string());
+            if let Err(e) = record_score(entry) {
+                msgbox(&format!("\nCould not record your score: {}\n", e), 24, &mut tcod.root);
+            }
+            if let Err(e) = delete_save(SAVE_SLOT) {
+                msgbox(&format!("\nCould not delete the save: {}\n", e), 24, &mut tcod.root);
+            }
+            break
+        }
+
+        if !objects[PLAYER].alive && game.mode == GameMode::Explorer {
+            respawn_player(objects, game);
+        }
+
+        if !objects[PLAYER].alive {
+            tcod.con.clear();
+            render_all(tcod, objects, game, false);
+            tcod.root.flush();
+            death_screen(&objects[PLAYER], game, &mut tcod.root);
+            // recorded here rather than in the death callback, so simulated games stay off the scoreboard
+            let entry = ScoreEntry::new(&objects[PLAYER], game, cause_of_death(game));
+            if let Err(e) = record_score(entry) {
+                msgbox(&format!("\nCould not record your score: {}\n", e), 24, &mut tcod.root);
+            }
+            if let Err(e) = delete_save(SAVE_SLOT) {
+                msgbox(&format!("\nCould not delete the save: {}\n", e), 24, &mut tcod.root);
             }
+            break
         }
 
     }
 
 }
 
+// a couple of frames of every visible monster stepping to its new tile, before the next key is read
+fn animate_monster_moves(tcod: &mut Tcod, objects: &[Object], game: &mut Game) {
+    let player = objects[PLAYER].pos();
+    tcod.fov.compute_fov(player.0, player.1, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+    let moved = objects.iter().any(|object| {
+        object.previous_pos.is_some_and(|pos| pos != object.pos()) && tcod.fov.is_in_fov(object.x, object.y)
+    });
+    if !moved {
+        return;
+    }
+
+    let frames = tcod.settings.animation_frames(MOVE_FRAMES);
+    for frame in 1..=frames {
+        tcod.tween = Some(frame as f32 / (frames + 1) as f32);
+        tcod.con.clear();
+        render_all(tcod, objects, game, false);
+        tcod.root.flush();
+    }
+    tcod.tween = None;
+}
+
+fn death_screen(player: &Object, game: &Game, root: &mut Root) {
+    let msg = format!(
+        "You died
+
+Dungeon level: {}
+Level: {}
+Experience: {}
+Turns survived: {}
+
+You were {}.",
+        game.dungeon_level,
+        player.level,
+        player.fighter.map_or(0, |f| f.xp),
+        game.turns,
+        cause_of_death(game),
+    );
+    msgbox(&msg, CHARACTER_SCREEN_WIDTH, root);
+}
+
+fn victory_screen(player: &Object, game: &Game, root: &mut Root) {
+    let msg = format!(
+        "Victory!
+
+The lich is destroyed and the dungeon falls silent.
+
+Dungeon level: {}
+Level: {}
+Experience: {}
+Turns taken: {}",
+        game.dungeon_level,
+        player.level,
+        player.fighter.map_or(0, |f| f.xp),
+        game.turns,
+    );
+    msgbox(&msg, CHARACTER_SCREEN_WIDTH, root);
+}
+
 fn msgbox(text: &str, width: i32, root: &mut Root) {
     let options: &[&str] = &[];
     menu(text, options, width, root);
 }
 
+fn pause_menu(game: &mut Game, tcod: &mut Tcod) -> PlayerAction {
+    let choices = &["Resume", "Save and quit to main menu", "Settings", "Abandon run"];
+    match menu("Paused\n", choices, SETTINGS_SCREEN_WIDTH, &mut tcod.root) {
+        Some(1) if confirm("Save and quit?", &mut tcod.root) => PlayerAction::Exit,
+        Some(2) => {
+            settings_menu(game, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+        Some(3) if confirm("Abandon this run? It can't be continued.", &mut tcod.root) => PlayerAction::Abandon,
+        _ => PlayerAction::DidntTakeTurn,
+    }
+}
+
+fn confirm(question: &str, root: &mut Root) -> bool {
+    menu(&format!("{}\n", question), &["Yes", "No"], SETTINGS_SCREEN_WIDTH, root) == Some(0)
+}
+
+// the next preset up from the current value, wrapping round to the first
+fn next_choice<T: PartialOrd + Copy>(choices: &[T], current: T) -> T {
+    choices.iter().cloned().find(|&choice| choice > current).unwrap_or(choices[0])
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+// a line of the settings menu, and what picking it does
+type SettingsOption = (fn(&Game, &Settings) -> String, fn(&mut Game, &mut Tcod));
+
+// whatever an option changes in Settings is saved straight away
+const SETTINGS_OPTIONS: &[SettingsOption] = &[
+    (|game, _| format!("Palette: {}", game.palette), |game, _| game.palette = game.palette.next()),
+    (|game, _| format!("Turn numbers in the log: {}", on_off(game.log_turns)), |game, _| game.log_turns = !game.log_turns),
+    (|game, _| format!("Animate monster moves: {}", on_off(game.animate_moves)), |game, _| game.animate_moves = !game.animate_moves),
+    (|game, _| format!("Damage numbers: {}", on_off(game.damage_numbers)), |game, _| game.damage_numbers = !game.damage_numbers),
+    (
+        |_, settings| format!("Pick up ammo on the way: {}", on_off(settings.auto_pickup)),
+        |_, tcod| tcod.settings.auto_pickup = !tcod.settings.auto_pickup,
+    ),
+    (|game, _| format!("Stairs compass: {}", on_off(game.stairs_compass)), |game, _| game.stairs_compass = !game.stairs_compass),
+    (|_, settings| format!("Font (next launch): {}", settings.font), |_, tcod| next_font(tcod)),
+    (
+        |_, settings| format!("Font layout: {}", settings.font_arrangement),
+        |_, tcod| tcod.settings.font_arrangement = tcod.settings.font_arrangement.next(),
+    ),
+    (
+        |_, settings| format!("Frame rate limit: {}", settings.fps),
+        |_, tcod| {
+            tcod.settings.fps = next_choice(FPS_CHOICES, tcod.settings.fps);
+            tcod::system::set_fps(tcod.settings.fps);
+        },
+    ),
+    (
+        |_, settings| format!("Animation speed: x{}", settings.animation_speed),
+        |_, tcod| tcod.settings.animation_speed = next_choice(ANIMATION_SPEEDS, tcod.settings.animation_speed),
+    ),
+];
+
+fn settings_menu(game: &mut Game, tcod: &mut Tcod) {
+    loop {
+        let labels: Vec<String> = SETTINGS_OPTIONS.iter().map(|(label, _)| label(game, &tcod.settings)).collect();
+        let (_, pick) = match menu("Settings\n", &labels, SETTINGS_SCREEN_WIDTH, &mut tcod.root) {
+            Some(choice) => SETTINGS_OPTIONS[choice],
+            None => break,
+        };
+
+        let before = tcod.settings.clone();
+        pick(game, tcod);
+        if tcod.settings != before {
+            if let Err(e) = save_settings(&tcod.settings) {
+                game.log.add_categorized(format!("Could not save the settings: {}", e), MessageCategory::System);
+            }
+        }
+    }
+}
+
+// the next font along from the current one, wrapping round
+fn next_font(tcod: &mut Tcod) {
+    let fonts = font_files();
+    if fonts.is_empty() {
+        msgbox("\nNo fonts found next to the game.\n", SETTINGS_SCREEN_WIDTH, &mut tcod.root);
+        return;
+    }
+    let current = fonts.iter().position(|font| *font == tcod.settings.font);
+    tcod.settings.font = fonts[current.map_or(0, |i| (i + 1) % fonts.len())].clone();
+}
+
+// every png next to the game that could be a font, which is all but the menu background
+fn font_files() -> Vec<String> {
+    let mut fonts: Vec<String> = std::fs::read_dir(".")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| name.ends_with(".png") && name != MENU_BACKGROUND)
+                .collect()
+        })
+        .unwrap_or_default();
+    fonts.sort();
+    fonts
+}
+
+fn font_layout(arrangement: FontArrangement) -> FontLayout {
+    match arrangement {
+        FontArrangement::Tcod => FontLayout::Tcod,
+        FontArrangement::AsciiInRow => FontLayout::AsciiInRow,
+        FontArrangement::AsciiInCol => FontLayout::AsciiInCol,
+    }
+}
+
+// --simulate [first seed] [turns] [runs]
+fn simulate(args: &[String]) {
+    let arg = |i: usize, default: u64| args.get(i).and_then(|a| a.parse().ok()).unwrap_or(default);
+    let first_seed = arg(0, 0);
+    let turns = arg(1, SIMULATION_TURNS);
+    let runs = arg(2, 1);
+
+    for seed in first_seed..first_seed + runs {
+        match panic::catch_unwind(|| run_simulation(seed, turns)) {
+            Ok(report) => println!("{}", report),
+            Err(_) => println!("seed {}: panicked", seed),
+        }
+    }
+}
+
 fn main_menu(tcod: &mut Tcod){
-    let img = tcod::image::Image::from_file("menu_background.png")
-        .ok()
-        .expect("Background image not found");
+    // the menu still works without its picture, just on a plain screen
+    let img = tcod::image::Image::from_file(MENU_BACKGROUND).ok();
 
     while !tcod.root.window_closed() {
-        // show the background image, at twice the regular console resolution
-        tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut tcod.root, (0, 0));
+        match img {
+            // show the background image, at twice the regular console resolution
+            Some(ref img) => tcod::image::blit_2x(img, (0, 0), (-1, -1), &mut tcod.root, (0, 0)),
+            None => {
+                tcod.root.set_default_background(colors::DARKEST_SEPIA);
+                tcod.root.clear();
+            }
+        }
 
         tcod.root.set_default_foreground(colors::LIGHT_YELLOW);
         tcod.root.print_ex(
-            SCREEN_WIDTH / 2,
-            SCREEN_HEIGHT / 2 - 4,
+            tcod.root.width() / 2,
+            tcod.root.height() / 2 - 4,
             BackgroundFlag::None,
             TextAlignment::Center,
             "TOMBS OF THE ANCIENT KINGS",
         );
         tcod.root.print_ex(
-            SCREEN_WIDTH / 2,
-            SCREEN_HEIGHT - 2,
+            tcod.root.width() / 2,
+            tcod.root.height() - 2,
             BackgroundFlag::None,
             TextAlignment::Center,
             "By Moi",
         );
 
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &["Play a new game", "Continue last game", "Hall of records", "Quit"];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
             Some(0) => {
-                let (mut objects, mut game) = new_game(tcod);
-                play_game(&mut objects, &mut game, tcod);
+                start_recording(tcod.config);
+                if let Some((mut objects, mut game)) = new_game(tcod) {
+                    play_game(&mut objects, &mut game, tcod);
+                }
+                if let Err(e) = finish_recording() {
+                    msgbox(&format!("\nCould not save the replay: {}\n", e), 24, &mut tcod.root);
+                }
             }
             Some(1) => {
-                match load_game() {
+                match load_game(SAVE_SLOT) {
                     Ok((mut objects, mut game)) => {
+                        tcod.inventory_cursors.clear();
                         initialise_fov(&game.map, tcod);
                         play_game(&mut objects, &mut game, tcod);
                     }
-                    Err(_e) => {
-                        msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+                    Err(e) => {
+                        let missing = e
+                            .downcast_ref::<std::io::Error>()
+                            .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound);
+                        if missing {
+                            msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+                        } else {
+                            msgbox(&format!("\nCould not load the game: {}\n", e), 24, &mut tcod.root);
+                        }
                         continue;
                     }
                 }
             }
             Some(2) => {
+                hall_of_records(&mut tcod.root);
+            }
+            Some(3) => {
                 break;
             }
             _ => {}
@@ -1612,22 +2051,73 @@ fn main_menu(tcod: &mut Tcod){
 
 fn main(){
 
+    set_spawn_table(load_spawn_table());
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--simulate") {
+        simulate(&args[2..]);
+        return;
+    }
+
+    // --replay [file] plays a recorded run back before going to the menu
+    let replay = if args.get(1).map(String::as_str) == Some("--replay") {
+        let path = args.get(2).map_or(REPLAY_FILE, String::as_str);
+        match load_replay(path) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                eprintln!("Cannot load the replay {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let config = replay.as_ref().map_or_else(load_config, |replay| replay.config);
+    let mut settings = load_settings();
+    // a font that has since been deleted would stop the window from opening at all
+    if !std::path::Path::new(&settings.font).is_file() {
+        settings.font = DEFAULT_FONT.into();
+        settings.font_arrangement = FontArrangement::default();
+    }
+    if !std::path::Path::new(&settings.font).is_file() {
+        eprintln!("Cannot find the font {}, run the game from the folder that holds it.", settings.font);
+        std::process::exit(1);
+    }
+
     let root = Root::initializer()
-        .font("./arial10x10.png", FontLayout::Tcod)
+        .font(&settings.font, font_layout(settings.font_arrangement))
         .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .size(config.screen_width, config.screen_height)
+        .fullscreen(settings.fullscreen)
         .title("Reflex")
         .init();
-    tcod::system::set_fps(LIMIT_FPS);
+    tcod::system::set_fps(settings.fps);
 
     let mut tcod = Tcod {
         root,
-        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+        con: Offscreen::new(config.screen_width, config.panel_y()),
+        panel: Offscreen::new(config.screen_width, PANEL_HEIGHT),
+        fov: FovMap::new(config.map_width, config.map_height),
         mouse: Default::default(),
+        config,
+        settings,
+        travel: None,
+        camera: (0, 0),
+        revealed: vec![],
+        tween: None,
+        inventory_cursors: HashMap::new(),
+        debug: args.iter().any(|arg| arg == "--debug"),
     };
 
+    save_recording_on_panic();
+    if let Some(replay) = replay {
+        start_playback(replay, tcod.settings.fps);
+        if let Some((mut objects, mut game)) = new_game(&mut tcod) {
+            play_game(&mut objects, &mut game, &mut tcod);
+        }
+    }
+
     main_menu(&mut tcod);
 
 }
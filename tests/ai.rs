@@ -0,0 +1,349 @@
+use roguelike::*;
+
+// only the listed tiles are in view
+struct Seen(Vec<(i32, i32)>);
+
+impl Visibility for Seen {
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.0.contains(&(x, y))
+    }
+}
+
+fn open_level(objects: Vec<Object>) -> (Vec<Object>, Game) {
+    let (_, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.map = vec![vec![Tile::empty(); 20]; 20];
+    (objects, game)
+}
+
+fn player(x: i32, y: i32) -> Object {
+    let mut player = Object::new(x, y, '@', "player", tcod::colors::WHITE, true);
+    player.alive = true;
+    player.fighter = Some(Fighter {
+        base_max_hp: 100,
+        hp: 100,
+        base_defense: 1,
+        base_power: 4,
+        on_death: DeathCallback::Player,
+        damage_type: DamageType::Physical,
+        resistances: Resistances::default(),
+        mana: 0,
+        max_mana: 0,
+        speed: NORMAL_SPEED,
+        energy: 0,
+        perception: PERCEPTION_RADIUS,
+        reckless: false,
+        xp: 0,
+    });
+    player
+}
+
+fn orc(x: i32, y: i32, ai: Ai) -> Object {
+    let mut orc = create_monster("orc", x, y, 1);
+    orc.ai = Some(ai);
+    orc
+}
+
+#[test]
+fn closest_monster_skips_what_cannot_be_seen() {
+    let awake = Ai::Basic { last_known: None, turns: 0 };
+    let (mut objects, game) = open_level(vec![player(5, 5), orc(6, 5, awake), orc(8, 5, awake)]);
+
+    let hidden_near = Seen(vec![(5, 5), (8, 5)]);
+    assert_eq!(closest_monster(5, &mut objects, &game.map, &hidden_near), Some(2));
+
+    let both = Seen(vec![(5, 5), (6, 5), (8, 5)]);
+    assert_eq!(closest_monster(5, &mut objects, &game.map, &both), Some(1));
+
+    assert_eq!(closest_monster(5, &mut objects, &game.map, &Seen(vec![])), None);
+}
+
+#[test]
+fn monster_in_view_closes_in_and_remembers_the_player() {
+    let awake = Ai::Basic { last_known: None, turns: 0 };
+    let (mut objects, mut game) = open_level(vec![player(5, 5), orc(8, 5, awake)]);
+    game.player_moved = true;
+
+    ai_take_turn(1, &mut game, &mut objects, &Seen(vec![(8, 5)]));
+
+    assert_eq!(objects[1].pos(), (7, 5));
+    assert_eq!(
+        objects[1].ai,
+        Some(Ai::Basic { last_known: Some((5, 5)), turns: MONSTER_CHASE_TURNS })
+    );
+}
+
+#[test]
+fn unaware_monsters_only_notice_a_noisy_player_within_their_perception() {
+    let awake = Ai::Basic { last_known: None, turns: 0 };
+    let (mut objects, mut game) = open_level(vec![player(5, 5), orc(8, 5, awake)]);
+    let in_view = Seen(vec![(8, 5)]);
+
+    ai_take_turn(1, &mut game, &mut objects, &in_view);
+    assert_eq!(objects[1].pos(), (8, 5));
+
+    game.player_moved = true;
+    game.sneaking = true;
+    ai_take_turn(1, &mut game, &mut objects, &in_view);
+    assert!(objects[1].is_unaware());
+
+    game.sneaking = false;
+    ai_take_turn(1, &mut game, &mut objects, &in_view);
+    assert_eq!(objects[1].pos(), (7, 5));
+    assert!(!objects[1].is_unaware());
+}
+
+#[test]
+fn a_ranged_hit_gives_the_player_away_from_beyond_perception() {
+    let awake = Ai::Basic { last_known: None, turns: 0 };
+    let (mut objects, mut game) = open_level(vec![player(2, 5), orc(15, 5, awake)]);
+    objects[1].fighter.as_mut().unwrap().hp = 100;
+    game.inventory.clear();
+    let mut sword = create_item(Item::Sword, 0, 0);
+    // a cursed one would stick to the hand
+    sword.equipment = Some(Equipment { slot: Slot::RightHand, equipped: true, max_hp_bonus: 0, power_bonus: 3, defense_bonus: 0, cursed: false });
+    game.inventory.push(sword);
+
+    assert!(throw_weapon(0, 1, &mut objects, &mut game));
+    assert_eq!(objects[1].ai, Some(Ai::Basic { last_known: Some((2, 5)), turns: MONSTER_CHASE_TURNS }));
+
+    ai_take_turn(1, &mut game, &mut objects, &Seen(vec![(15, 5)]));
+    assert_eq!(objects[1].pos(), (14, 5));
+}
+
+#[test]
+fn monster_out_of_view_heads_for_the_last_known_spot() {
+    let hunting = Ai::Basic { last_known: Some((10, 10)), turns: 2 };
+    let (mut objects, mut game) = open_level(vec![player(1, 1), orc(10, 5, hunting)]);
+
+    ai_take_turn(1, &mut game, &mut objects, &Seen(vec![]));
+
+    assert_eq!(objects[1].pos(), (10, 6));
+    assert_eq!(objects[1].ai, Some(Ai::Basic { last_known: Some((10, 10)), turns: 1 }));
+}
+
+#[test]
+fn careful_monsters_go_round_chasms_but_trolls_charge_straight_in() {
+    let hunting = |x, y| Ai::Basic { last_known: Some((x, y)), turns: MONSTER_CHASE_TURNS };
+    let mut troll = create_monster("troll", 8, 15, 1);
+    troll.ai = Some(hunting(5, 15));
+    let (mut objects, mut game) = open_level(vec![player(5, 5), orc(8, 5, hunting(5, 5)), troll]);
+    // a rift down the middle, crossable only at the top
+    for y in 3..20 {
+        game.map[7][y] = Tile::chasm();
+    }
+    assert_eq!(tile_cost(&game.map[7][5]), Some(HAZARD_COST));
+    assert_eq!(tile_cost(&Tile::wall()), None);
+
+    ai_take_turn(1, &mut game, &mut objects, &Seen(vec![]));
+    ai_take_turn(2, &mut game, &mut objects, &Seen(vec![]));
+
+    assert_eq!(objects[1].pos(), (8, 4));
+    assert_eq!(objects[2].pos(), (7, 15));
+    assert!(objects[2].over_chasm(&game.map));
+}
+
+#[test]
+fn mana_regenerates_up_to_the_maximum() {
+    let (mut objects, mut game) = open_level(vec![player(5, 5)]);
+    if let Some(ref mut fighter) = objects[PLAYER].fighter {
+        fighter.max_mana = 2;
+    }
+
+    for _ in 0..MANA_REGEN_INTERVAL * 5 {
+        game.turns += 1;
+        turn_events(&mut objects, &mut game, &Seen(vec![]));
+    }
+    assert_eq!(objects[PLAYER].fighter.unwrap().mana, 2);
+}
+
+#[test]
+fn explorer_mode_brings_the_player_back_at_the_entrance_for_half_their_xp() {
+    let (mut objects, mut game) = open_level(vec![player(5, 5)]);
+    game.mode = GameMode::Explorer;
+    game.entrance = Some((2, 2));
+    objects[PLAYER].fighter.as_mut().unwrap().xp = 100;
+    objects[PLAYER].add_effect(StatusEffect { kind: EffectKind::Slow, amount: 1, turns: 20 });
+
+    objects[PLAYER].take_damage(1000, DamageType::Physical, None, &mut game);
+    assert!(!objects[PLAYER].alive);
+
+    respawn_player(&mut objects, &mut game);
+    assert!(objects[PLAYER].alive);
+    assert_eq!(objects[PLAYER].pos(), (2, 2));
+    assert_eq!(objects[PLAYER].fighter.unwrap().hp, objects[PLAYER].max_hp(&game));
+    assert_eq!(objects[PLAYER].fighter.unwrap().xp, 100 * (100 - EXPLORER_XP_PENALTY) / 100);
+    assert!(objects[PLAYER].effects.is_empty());
+}
+
+#[test]
+fn wandering_monsters_turn_up_out_of_sight_unless_the_difficulty_turns_them_off() {
+    let interval = Difficulty::Normal.wandering_monster_interval().unwrap();
+    assert!(Difficulty::Hard.wandering_monster_interval().unwrap() < interval);
+    let (mut objects, mut game) = open_level(vec![player(5, 5), orc(15, 15, Ai::Sleeping)]);
+    let in_view = Seen((0..20).flat_map(|x| (0..10).map(move |y| (x, y))).collect());
+
+    game.turns = interval;
+    turn_events(&mut objects, &mut game, &in_view);
+    assert_eq!(objects.len(), 3);
+    assert!(objects[2].y >= 10);
+
+    game.difficulty = Difficulty::Easy;
+    assert_eq!(game.difficulty.wandering_monster_interval(), None);
+    turn_events(&mut objects, &mut game, &in_view);
+    assert_eq!(objects.len(), 3);
+}
+
+#[test]
+fn trolls_regenerate_and_hurt_orcs_enrage() {
+    let mut troll = create_monster("troll", 15, 15, 1);
+    troll.ai = Some(Ai::Sleeping);
+    let (mut objects, mut game) = open_level(vec![player(1, 1), troll, orc(15, 1, Ai::Sleeping)]);
+    objects[1].fighter.as_mut().unwrap().hp = 1;
+    objects[2].fighter.as_mut().unwrap().hp = 1;
+    let orc_power = objects[2].power(&game);
+
+    ai_take_turn(1, &mut game, &mut objects, &Seen(vec![]));
+    ai_take_turn(2, &mut game, &mut objects, &Seen(vec![]));
+    assert_eq!(objects[1].fighter.unwrap().hp, 1 + TROLL_REGENERATION);
+    assert_eq!(objects[2].power(&game), orc_power + ORC_RAGE_POWER);
+}
+
+#[test]
+fn walls_block_the_line_of_sight() {
+    let (_, mut game) = open_level(vec![]);
+    assert!(has_line_of_sight(&game.map, (2, 5), (8, 5)));
+
+    game.map[5][5] = Tile::wall();
+    assert!(!has_line_of_sight(&game.map, (2, 5), (8, 5)));
+    assert!(!has_line_of_sight(&game.map, (8, 5), (2, 5)));
+    // the wall itself can still be targeted
+    assert!(has_line_of_sight(&game.map, (2, 5), (5, 5)));
+    assert!(has_line_of_sight(&game.map, (2, 4), (8, 4)));
+}
+
+#[test]
+fn closest_monster_needs_a_clear_shot() {
+    let awake = Ai::Basic { last_known: None, turns: 0 };
+    let (mut objects, mut game) = open_level(vec![player(5, 5), orc(7, 5, awake), orc(5, 8, awake)]);
+    game.map[6][5] = Tile::wall();
+
+    let everything = Seen(vec![(5, 5), (7, 5), (5, 8)]);
+    assert_eq!(closest_monster(5, &mut objects, &game.map, &everything), Some(2));
+
+    game.map[5][7] = Tile::wall();
+    assert_eq!(closest_monster(5, &mut objects, &game.map, &everything), None);
+}
+
+#[test]
+fn big_monsters_block_and_need_room_for_their_whole_footprint() {
+    let mut boss = create_monster("boss", 5, 5, 1);
+    boss.size = 2;
+    let (mut objects, mut game) = open_level(vec![player(1, 1), boss]);
+
+    assert!(is_blocked(6, 6, &game.map, &objects));
+    assert!(!is_blocked(7, 6, &game.map, &objects));
+    objects[PLAYER].set_pos(7, 6);
+    assert_eq!(objects[PLAYER].distance_to(&objects[1]), 1.0);
+
+    // the right-hand column would land on the wall
+    game.map[8][5] = Tile::wall();
+    objects[PLAYER].set_pos(1, 1);
+    move_by(1, 2, 0, &game.map, &mut objects);
+    assert_eq!(objects[1].pos(), (5, 5));
+    move_by(1, 1, 0, &game.map, &mut objects);
+    assert_eq!(objects[1].pos(), (6, 5));
+}
+
+#[test]
+fn bosses_only_summon_where_the_whole_minion_fits() {
+    let mut spawns = SpawnTable::default();
+    spawns.monsters.iter_mut().find(|template| template.key == "poulet").unwrap().size = 2;
+    set_spawn_table(spawns);
+
+    let mut boss = create_monster("boss", 5, 5, 1);
+    boss.size = 2;
+    boss.ai = Some(Ai::Boss { seen: true, summon_cooldown: 0 });
+    let (mut objects, mut game) = open_level(vec![player(1, 1), boss]);
+    ai_take_turn(1, &mut game, &mut objects, &Seen(vec![(5, 5)]));
+    set_spawn_table(SpawnTable::default());
+
+    let minion = &game.pending_objects[0];
+    assert_eq!(minion.size, 2);
+    assert!(footprint_fits(minion.x, minion.y, minion.size, &game.map, &objects, None));
+}
+
+#[test]
+fn moving_towards_your_own_tile_stays_put() {
+    let (mut objects, game) = open_level(vec![player(5, 5), orc(8, 5, Ai::Sleeping)]);
+
+    move_towards(1, 8, 5, &game.map, &mut objects);
+    assert_eq!(objects[1].pos(), (8, 5));
+
+    // far off targets still only take a single step
+    move_towards(1, 19, 0, &game.map, &mut objects);
+    assert_eq!(objects[1].pos(), (9, 5));
+}
+
+#[test]
+fn speed_decides_how_often_a_monster_acts() {
+    let hunting = Ai::Basic { last_known: Some((5, 5)), turns: MONSTER_CHASE_TURNS };
+    let mut fast = orc(15, 5, hunting);
+    fast.fighter.as_mut().unwrap().speed = 2 * NORMAL_SPEED;
+    let mut slow = orc(15, 12, hunting);
+    slow.fighter.as_mut().unwrap().speed = NORMAL_SPEED / 2;
+    let (mut objects, mut game) = open_level(vec![player(5, 5), fast, slow]);
+    let everything = Seen((0..20).flat_map(|x| (0..20).map(move |y| (x, y))).collect());
+
+    end_player_turn(&mut objects, &mut game, &everything);
+    assert_eq!(objects[1].pos(), (13, 5));
+    assert_eq!(objects[2].pos(), (15, 12));
+
+    end_player_turn(&mut objects, &mut game, &everything);
+    assert_eq!(objects[1].pos(), (11, 5));
+    assert_ne!(objects[2].pos(), (15, 12));
+}
+
+#[test]
+fn a_hasted_player_gets_two_moves_for_each_monster_move() {
+    let hunting = Ai::Basic { last_known: Some((5, 5)), turns: MONSTER_CHASE_TURNS };
+    let (mut objects, mut game) = open_level(vec![player(5, 5), orc(15, 5, hunting)]);
+    let everything = Seen((0..20).flat_map(|x| (0..20).map(move |y| (x, y))).collect());
+    game.inventory.push(create_item(Item::HastePotion, 0, 0));
+    apply_item(Item::HastePotion, 0, &mut objects, &mut game);
+    assert_eq!(objects[PLAYER].speed(), 2 * NORMAL_SPEED);
+
+    end_player_turn(&mut objects, &mut game, &everything);
+    assert_eq!(objects[1].pos(), (15, 5));
+    end_player_turn(&mut objects, &mut game, &everything);
+    assert_eq!(objects[1].pos(), (14, 5));
+}
+
+#[test]
+fn cold_blows_slow_their_target() {
+    let (mut objects, mut game) = open_level(vec![player(5, 5)]);
+    let mut lich = create_monster("lich", 6, 5, 1);
+
+    // misses are random, so give it a fixed run of blows rather than waiting forever for a hit
+    seed_rng(1);
+    for _ in 0..50 {
+        objects[PLAYER].fighter.as_mut().unwrap().hp = 1000;
+        lich.attack(&mut objects[PLAYER], &mut game);
+    }
+    assert!(objects[PLAYER].effect_total(EffectKind::Slow) > 0);
+    assert!(objects[PLAYER].speed() < NORMAL_SPEED);
+}
+
+#[test]
+fn resistances_cut_the_damage_taken_and_weaknesses_add_to_it() {
+    let (_, mut game) = open_level(vec![player(5, 5)]);
+    let mut elemental = create_monster("fire elemental", 6, 5, 1);
+    elemental.fighter.as_mut().unwrap().hp = 100;
+    let hp = |monster: &Object| monster.fighter.unwrap().hp;
+
+    elemental.take_damage(20, DamageType::Fire, None, &mut game);
+    assert_eq!(hp(&elemental), 95);
+    elemental.take_damage(20, DamageType::Cold, None, &mut game);
+    assert_eq!(hp(&elemental), 65);
+    elemental.take_damage(20, DamageType::Physical, None, &mut game);
+    assert_eq!(hp(&elemental), 45);
+}
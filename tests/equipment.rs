@@ -0,0 +1,121 @@
+use roguelike::*;
+
+fn chainmail(equipped: bool, cursed: bool) -> Object {
+    let mut chainmail = Object::new(0, 0, '░', "chainmail armor", tcod::colors::COPPER, false);
+    chainmail.item = Some(Item::Chest);
+    let bonus = if cursed { -10 } else { 10 };
+    chainmail.equipment = Some(Equipment {
+        equipped,
+        slot: Slot::Chest,
+        power_bonus: 0,
+        defense_bonus: 2,
+        max_hp_bonus: bonus,
+        cursed,
+    });
+    chainmail
+}
+
+#[test]
+fn max_hp_counts_only_equipped_gear() {
+    let (objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    let starting_max_hp = objects[PLAYER].max_hp(&game);
+
+    game.inventory.push(chainmail(false, false));
+    assert_eq!(objects[PLAYER].max_hp(&game), starting_max_hp);
+
+    game.inventory.last_mut().unwrap().equipment.as_mut().unwrap().equipped = true;
+    assert_eq!(objects[PLAYER].max_hp(&game), starting_max_hp + 10);
+}
+
+#[test]
+fn a_curse_stays_hidden_until_the_gear_is_worn() {
+    let (_, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.inventory.clear();
+    let mut cursed = chainmail(false, true);
+    cursed.identified = false;
+    game.inventory.push(cursed);
+    assert_eq!(equipment_comparison(&game.inventory[0], &game), "bonuses unknown until worn");
+
+    game.inventory[0].equip(&mut game.log);
+    game.inventory[0].equipment.as_mut().unwrap().equipped = false;
+    assert_eq!(equipment_comparison(&game.inventory[0], &game), "+0 power (now +0), +2 defense (now +0), -10 hp (now +0)");
+}
+
+#[test]
+fn the_breakdown_lists_what_each_worn_item_adds() {
+    let (_, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.inventory = vec![chainmail(true, false), chainmail(false, false)];
+    assert_eq!(equipment_breakdown(&game), vec!["chainmail armor: +2 def, +10 hp".to_string()]);
+
+    game.inventory.clear();
+    assert!(equipment_breakdown(&game).is_empty());
+}
+
+#[test]
+fn cursed_gear_lowers_max_hp() {
+    let (objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    let starting_max_hp = objects[PLAYER].max_hp(&game);
+
+    game.inventory.push(chainmail(true, true));
+    assert_eq!(objects[PLAYER].max_hp(&game), starting_max_hp - 10);
+}
+
+#[test]
+fn healing_fills_up_to_the_equipped_maximum() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.inventory.push(chainmail(true, false));
+    objects[PLAYER].fighter.as_mut().unwrap().hp = 1;
+
+    objects[PLAYER].cast("heal", 1000, &mut game);
+    assert_eq!(objects[PLAYER].fighter.unwrap().hp, objects[PLAYER].max_hp(&game));
+}
+
+#[test]
+fn classes_change_the_starting_stats_and_kit() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    let stock_kit = game.inventory.len();
+    PlayerClass::Mage.outfit(&mut objects[PLAYER], &mut game);
+
+    assert_eq!(game.class, PlayerClass::Mage);
+    assert_eq!(objects[PLAYER].max_hp(&game), PlayerClass::Mage.stats().0);
+    assert!(game.inventory.len() > stock_kit);
+    assert!(game.inventory.iter().any(|item| item.item == Some(Item::Lightning)));
+    assert!(game.is_identified(Item::Lightning));
+}
+
+#[test]
+fn the_warrior_starts_with_a_sword_instead_of_the_dagger() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    PlayerClass::Warrior.outfit(&mut objects[PLAYER], &mut game);
+
+    let blades: Vec<&str> = game
+        .inventory
+        .iter()
+        .filter(|item| item.item == Some(Item::Sword))
+        .map(|item| item.name.as_str())
+        .collect();
+    assert_eq!(blades, vec!["sword"]);
+}
+
+#[test]
+fn a_thrown_weapon_hurts_the_target_and_lands_at_its_feet() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.inventory.clear();
+    let mut sword = create_item(Item::Sword, 0, 0);
+    sword.equipment = Some(Equipment { slot: Slot::RightHand, equipped: true, max_hp_bonus: 0, power_bonus: 3, defense_bonus: 0, cursed: false });
+    game.inventory.push(sword);
+
+    let mut orc = create_monster("orc", 20, 20, 1);
+    orc.fighter.as_mut().unwrap().resistances = Resistances::default();
+    let hp = orc.fighter.unwrap().hp;
+    objects.push(orc);
+    let orc = objects.len() - 1;
+
+    assert_eq!(equipped_weapon(&game.inventory), Some(0));
+    assert!(throw_weapon(0, orc, &mut objects, &mut game));
+    assert!(game.inventory.is_empty());
+    assert_eq!(objects[orc].fighter.unwrap().hp, hp - 3);
+    let landed = objects.last().unwrap();
+    assert_eq!((landed.item, landed.pos()), (Some(Item::Sword), (20, 20)));
+    assert!(!landed.equipment.unwrap().equipped);
+}
@@ -0,0 +1,140 @@
+use roguelike::*;
+
+fn base_power(objects: &[Object]) -> i32 {
+    objects[PLAYER].fighter.unwrap().base_power
+}
+
+#[test]
+fn attack_buff_raises_base_power() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    let before = base_power(&objects);
+
+    let result = cast_attack_buff(0, &mut objects, &mut game);
+    assert!(matches!(result, UseResult::UsedUp));
+    assert_eq!(base_power(&objects), before + ATTACK_BUFF);
+}
+
+#[test]
+fn attack_buff_stops_at_the_cap() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects[PLAYER].fighter.as_mut().unwrap().base_power = PLAYER_MAX_ATTACK - 1;
+
+    cast_attack_buff(0, &mut objects, &mut game);
+    assert_eq!(base_power(&objects), PLAYER_MAX_ATTACK);
+
+    // already capped: the scroll is kept
+    let result = cast_attack_buff(0, &mut objects, &mut game);
+    assert!(matches!(result, UseResult::Cancelled));
+    assert_eq!(base_power(&objects), PLAYER_MAX_ATTACK);
+}
+
+#[test]
+fn items_do_whatever_their_template_says() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects[PLAYER].fighter.as_mut().unwrap().hp = 1;
+
+    let mut spawns = SpawnTable::default();
+    spawns.items.iter_mut().find(|template| template.item == Item::Ration).unwrap().on_use = Some(UseCallback::Heal);
+    set_spawn_table(spawns);
+    let result = apply_item(Item::Ration, 0, &mut objects, &mut game);
+    set_spawn_table(SpawnTable::default());
+
+    assert!(matches!(result, UseResult::UsedUp));
+    assert!(objects[PLAYER].fighter.unwrap().hp > 1);
+}
+
+#[test]
+fn a_full_pack_still_takes_more_of_a_stack() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.inventory.clear();
+    game.inventory.push(create_item(Item::Ration, 0, 0));
+    while game.inventory.len() < INVENTORY_LIMIT {
+        game.inventory.push(create_item(Item::PowerRing, 0, 0));
+    }
+
+    objects.push(create_item(Item::Targe, objects[PLAYER].x, objects[PLAYER].y));
+    let targe = objects.len() - 1;
+    pick_item_up(targe, &mut objects, &mut game);
+    assert_eq!(game.inventory.len(), INVENTORY_LIMIT);
+    assert_eq!(objects[targe].item, Some(Item::Targe));
+
+    objects.push(create_item(Item::Ration, objects[PLAYER].x, objects[PLAYER].y));
+    pick_item_up(objects.len() - 1, &mut objects, &mut game);
+    assert_eq!(game.inventory.len(), INVENTORY_LIMIT);
+    assert_eq!(game.inventory[0].quantity, 2);
+}
+
+#[test]
+fn a_heavy_pack_slows_the_player_and_refuses_more_past_the_limit() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::Normal);
+    game.inventory.clear();
+    let capacity = carry_capacity(&objects[PLAYER]);
+    while carried_weight(&game) <= capacity {
+        game.inventory.push(create_item(Item::Chest, 0, 0));
+    }
+    assert!(is_burdened(&objects, &game));
+    assert_eq!(player_speed(&objects, &game), NORMAL_SPEED - BURDENED_SPEED_PENALTY);
+
+    while carried_weight(&game) <= capacity * MAX_LOAD_PERCENT / 100 - 15 {
+        game.inventory.push(create_item(Item::Chest, 0, 0));
+    }
+    let carried = game.inventory.len();
+    objects.push(create_item(Item::Chest, objects[PLAYER].x, objects[PLAYER].y));
+    pick_item_up(objects.len() - 1, &mut objects, &mut game);
+    assert_eq!(game.inventory.len(), carried);
+
+    // easy games don't count weight at all
+    game.difficulty = Difficulty::Easy;
+    assert!(!is_burdened(&objects, &game));
+    pick_item_up(objects.len() - 1, &mut objects, &mut game);
+    assert_eq!(game.inventory.len(), carried + 1);
+}
+
+#[test]
+fn auto_pickup_grabs_ammo_but_leaves_everything_else() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects.truncate(1);
+    game.map = vec![vec![Tile::empty(); 20]; 20];
+    game.inventory.clear();
+    objects[PLAYER].set_pos(5, 5);
+    objects.push(create_item(Item::Arrow, 6, 5));
+    objects.push(create_item(Item::Heal, 7, 5));
+
+    player_move_or_attack(1, 0, true, &mut objects, &mut game);
+    player_move_or_attack(1, 0, true, &mut objects, &mut game);
+    assert_eq!(game.inventory.len(), 1);
+    assert_eq!(game.inventory[0].item, Some(Item::Arrow));
+    assert_eq!(objects.len(), 2);
+
+    objects.push(create_item(Item::Arrow, 8, 5));
+    player_move_or_attack(1, 0, false, &mut objects, &mut game);
+    assert_eq!(objects.len(), 3);
+}
+
+#[test]
+fn drop_all_spreads_the_unworn_pack_one_item_to_a_tile() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects.truncate(1);
+    game.map = vec![vec![Tile::empty(); 20]; 20];
+    objects[PLAYER].set_pos(5, 5);
+    objects.push(create_item(Item::Heal, 5, 5));
+    game.inventory.clear();
+    let mut sword = create_item(Item::Sword, 0, 0);
+    sword.equipment.as_mut().unwrap().equipped = true;
+    game.inventory.push(sword);
+    for _ in 0..3 {
+        game.inventory.push(create_item(Item::Ration, 0, 0));
+    }
+
+    assert_eq!(drop_all(&mut objects, &mut game), 3);
+    assert_eq!(game.inventory.len(), 1);
+    assert_eq!(game.inventory[0].item, Some(Item::Sword));
+
+    let mut spots: Vec<(i32, i32)> = objects[1..].iter().map(Object::pos).collect();
+    spots.sort();
+    spots.dedup();
+    assert_eq!(spots.len(), 4);
+    assert!(spots.iter().all(|&(x, y)| (x - 5).abs() <= 1 && (y - 5).abs() <= 1));
+
+    assert_eq!(drop_all(&mut objects, &mut game), 0);
+}
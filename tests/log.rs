@@ -0,0 +1,47 @@
+use roguelike::*;
+use tcod::colors;
+
+#[test]
+fn repeated_messages_are_counted() {
+    let mut log = Messages::default();
+    log.add("orc attacks player for 3 hit points.", colors::WHITE);
+    log.add("orc attacks player for 3 hit points.", colors::WHITE);
+    log.add("orc attacks player for 3 hit points.", colors::WHITE);
+
+    assert_eq!(log.len(), 1);
+    assert_eq!(log.iter().next().unwrap().text, "orc attacks player for 3 hit points. (x3)");
+}
+
+#[test]
+fn different_messages_or_colors_are_kept_apart() {
+    let mut log = Messages::default();
+    log.add("You are starving!", colors::RED);
+    log.add("You are starving!", colors::YELLOW);
+    log.add("You are getting hungry.", colors::YELLOW);
+
+    assert_eq!(log.len(), 3);
+}
+
+#[test]
+fn messages_from_older_saves_still_load() {
+    let color = serde_json::to_value(colors::RED).unwrap();
+    let old = serde_json::json!([["You died, see you another time!", color]]);
+
+    let log: Messages = serde_json::from_value(old).unwrap();
+    let entry = log.iter().next().unwrap();
+    assert_eq!(entry.text, "You died, see you another time!");
+    assert_eq!(entry.turn, 0);
+}
+
+#[test]
+fn damage_numbers_are_queued_only_when_turned_on() {
+    let (_, mut game) = new_world(&Config::default(), Difficulty::default());
+    let mut troll = create_monster("troll", 7, 8, 1);
+
+    troll.take_damage(5, DamageType::Physical, None, &mut game);
+    assert!(game.damage_labels.is_empty());
+
+    game.damage_numbers = true;
+    troll.take_damage(5, DamageType::Physical, None, &mut game);
+    assert_eq!(game.damage_labels, vec![DamageLabel { x: 7, y: 8, amount: 5, age: 0 }]);
+}
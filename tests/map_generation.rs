@@ -0,0 +1,272 @@
+use roguelike::*;
+
+fn player() -> Object {
+    let mut player = Object::new(0, 0, '@', "player", tcod::colors::WHITE, true);
+    player.alive = true;
+    player
+}
+
+// tiles reachable on foot from the start, walls being the only obstacle
+fn reachable(map: &Map, start: (i32, i32)) -> Vec<Vec<bool>> {
+    let (width, height) = map_size(map);
+    let mut seen = vec![vec![false; height as usize]; width as usize];
+    let mut stack = vec![start];
+    while let Some((x, y)) = stack.pop() {
+        if x < 0 || y < 0 || x >= width || y >= height {
+            continue;
+        }
+        if seen[x as usize][y as usize] || map[x as usize][y as usize].blocked {
+            continue;
+        }
+        seen[x as usize][y as usize] = true;
+        stack.extend(&[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+    }
+    seen
+}
+
+// whether one of the vault prefabs has been stamped anywhere on the map
+fn has_vault(map: &Map) -> bool {
+    let (width, height) = map_size(map);
+    VAULTS.iter().any(|prefab| {
+        let (tiles, _) = parse_prefab(prefab);
+        let (vault_width, vault_height) = (tiles.len() as i32, tiles[0].len() as i32);
+        (0..=width - vault_width).any(|origin_x| {
+            (0..=height - vault_height).any(|origin_y| {
+                tiles.iter().enumerate().all(|(x, column)| {
+                    column.iter().enumerate().all(|(y, tile)| {
+                        map[origin_x as usize + x][origin_y as usize + y].blocked == tile.blocked
+                    })
+                })
+            })
+        })
+    })
+}
+
+#[test]
+fn bsp_rooms_do_not_overlap() {
+    for seed in 0..50 {
+        seed_rng(seed);
+        let mut objects = vec![player()];
+        let generated = BspGenerator.generate(&mut objects, 2, Difficulty::default(), &Config::default());
+
+        for (i, a) in generated.rooms.iter().enumerate() {
+            for b in &generated.rooms[i + 1..] {
+                assert!(!a.intersect_with(b), "seed {}: {:?} overlaps {:?}", seed, a, b);
+            }
+        }
+    }
+}
+
+#[test]
+fn bsp_rooms_are_connected() {
+    for seed in 0..50 {
+        seed_rng(seed);
+        let mut objects = vec![player()];
+        let generated = BspGenerator.generate(&mut objects, 2, Difficulty::default(), &Config::default());
+        let seen = reachable(&generated.map, generated.player_start);
+
+        for room in &generated.rooms {
+            let (x, y) = room.center();
+            assert!(seen[x as usize][y as usize], "seed {}: {:?} is cut off", seed, room);
+        }
+        let (x, y) = generated.stairs;
+        assert!(seen[x as usize][y as usize], "seed {}: the stairs are cut off", seed);
+    }
+}
+
+#[test]
+fn prefabs_parse_into_tiles_and_markers() {
+    let (tiles, markers) = parse_prefab("###\n#!B\n.m#");
+
+    assert_eq!(tiles.len(), 3);
+    assert_eq!(tiles[0].len(), 3);
+    assert!(tiles[0][0].blocked);
+    assert!(!tiles[1][1].blocked);
+    assert!(!tiles[0][2].blocked);
+    assert_eq!(
+        markers,
+        vec![SpawnMarker::Item(1, 1), SpawnMarker::Boss(2, 1), SpawnMarker::Monster(1, 2)]
+    );
+}
+
+#[test]
+fn vault_contents_can_be_reached() {
+    let mut vaults = 0;
+    for seed in 0..50 {
+        seed_rng(seed);
+        let mut objects = vec![player()];
+        let (map, _) = make_map(&mut objects, 6, GeneratorKind::Rooms, Difficulty::default(), &Config::default());
+        let seen = reachable(&map, objects[PLAYER].pos());
+        if has_vault(&map) {
+            vaults += 1;
+        }
+
+        for object in &objects[1..] {
+            let (x, y) = object.pos();
+            assert!(seen[x as usize][y as usize], "seed {}: the {} at {:?} is cut off", seed, object.name, (x, y));
+        }
+    }
+    // otherwise there was nothing to check
+    assert!(vaults > 0);
+}
+
+#[test]
+fn boss_levels_are_sealed_until_the_guardian_dies() {
+    seed_rng(7);
+    let mut objects = vec![player()];
+    make_map(&mut objects, BOSS_LEVEL_INTERVAL, GeneratorKind::Rooms, Difficulty::default(), &Config::default());
+    assert!(stairs_sealed(&objects));
+
+    let guardian = objects.iter_mut().find(|object| object.guardian).unwrap();
+    guardian.alive = false;
+    assert!(!stairs_sealed(&objects));
+
+    let mut objects = vec![player()];
+    make_map(&mut objects, BOSS_LEVEL_INTERVAL + 1, GeneratorKind::Rooms, Difficulty::default(), &Config::default());
+    assert!(!stairs_sealed(&objects));
+}
+
+#[test]
+fn stairs_and_the_start_are_always_on_open_unoccupied_floor() {
+    let config = Config::default();
+    for seed in 0..5 {
+        seed_rng(seed);
+        for &generator in &[GeneratorKind::Rooms, GeneratorKind::Caves, GeneratorKind::Bsp] {
+            for level in 1..FINAL_LEVEL {
+                let mut objects = vec![player()];
+                let (map, _) = make_map(&mut objects, level, generator, Difficulty::Hard, &config);
+                let monster_on = |(x, y): (i32, i32)| objects[1..].iter().any(|o| o.blocks && o.occupies(x, y));
+
+                let (x, y) = stairs_position(&objects).unwrap();
+                let stairs = &map[x as usize][y as usize];
+                assert!(!stairs.blocked && !stairs.chasm, "seed {} {:?} level {}: stairs on {:?}", seed, generator, level, (x, y));
+                assert!(!monster_on((x, y)), "seed {} {:?} level {}: monster on the stairs", seed, generator, level);
+                assert!(!monster_on(objects[PLAYER].pos()), "seed {} {:?} level {}: monster on the start", seed, generator, level);
+            }
+        }
+    }
+}
+
+#[test]
+fn slaying_the_lich_on_the_final_level_wins() {
+    seed_rng(3);
+    let config = Config::default();
+    let (mut objects, mut game) = new_world(&config, Difficulty::default());
+    for _ in 1..FINAL_LEVEL + 2 {
+        descend(&mut objects, &mut game, &config);
+    }
+    assert_eq!(game.dungeon_level, FINAL_LEVEL);
+    assert_eq!(stairs_position(&objects), None);
+
+    let lich = objects.iter_mut().find(|object| object.name == "lich").unwrap();
+    lich.take_damage(10_000, DamageType::Physical, None, &mut game);
+    assert!(game.won);
+}
+
+#[test]
+fn traps_start_hidden_on_open_floor_below_the_first_level() {
+    let traps = |map: &Map| map.iter().flatten().filter_map(|tile| tile.trap.map(|trap| (trap, tile.blocked))).collect::<Vec<_>>();
+    let mut placed = 0;
+    for seed in 0..20 {
+        seed_rng(seed);
+        let mut objects = vec![player()];
+        let (map, _) = make_map(&mut objects, 1, GeneratorKind::Rooms, Difficulty::default(), &Config::default());
+        assert!(traps(&map).is_empty());
+
+        let mut objects = vec![player()];
+        let (map, _) = make_map(&mut objects, 6, GeneratorKind::Rooms, Difficulty::default(), &Config::default());
+        let (x, y) = objects[PLAYER].pos();
+        assert!(map[x as usize][y as usize].trap.is_none());
+        for (trap, blocked) in traps(&map) {
+            assert!(!trap.found && !blocked);
+            placed += 1;
+        }
+    }
+    assert!(placed > 0);
+}
+
+#[test]
+fn caves_get_thickets_and_pools_without_cutting_anything_off() {
+    let mut features = (0, 0);
+    for seed in 0..20 {
+        seed_rng(seed);
+        let mut objects = vec![player()];
+        let generated = CaveGenerator.generate(&mut objects, 3, Difficulty::default(), &Config::default());
+        let seen = reachable(&generated.map, generated.player_start);
+
+        let (width, height) = map_size(&generated.map);
+        for x in 0..width {
+            for y in 0..height {
+                let tile = generated.map[x as usize][y as usize];
+                match (tile.blocked, tile.block_sight) {
+                    (false, true) => features.0 += 1,
+                    (true, false) => features.1 += 1,
+                    _ => {}
+                }
+                if !tile.blocked {
+                    assert!(seen[x as usize][y as usize], "seed {}: ({}, {}) is cut off", seed, x, y);
+                }
+            }
+        }
+    }
+    assert!(features.0 > 0 && features.1 > 0);
+}
+
+#[test]
+fn thickets_block_only_sight_and_pools_only_movement() {
+    let mut map = vec![vec![Tile::empty(); 12]; 12];
+    map[5][3] = Tile::pool();
+    map[5][7] = Tile::thicket();
+    let objects = vec![player()];
+
+    assert!(is_blocked(5, 3, &map, &objects));
+    assert!(!is_blocked(5, 7, &map, &objects));
+    assert!(has_line_of_sight(&map, (2, 3), (8, 3)));
+    assert!(!has_line_of_sight(&map, (2, 7), (8, 7)));
+
+    let mut fov = tcod::map::Map::new(12, 12);
+    fill_fov(&map, &mut fov);
+    fov.compute_fov(2, 3, 10, true, tcod::map::FovAlgorithm::Basic);
+    assert!(fov.is_in_fov(8, 3));
+    fov.compute_fov(2, 7, 10, true, tcod::map::FovAlgorithm::Basic);
+    assert!(!fov.is_in_fov(8, 7));
+}
+
+#[test]
+fn the_ascii_dump_shows_walls_floor_stairs_and_creatures() {
+    let (_, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.map = vec![vec![Tile::wall(); 3]; 4];
+    game.map[1][1] = Tile::empty();
+    game.map[2][1] = Tile::empty();
+    let mut stairs = Object::new(2, 1, '<', "stairs", tcod::colors::WHITE, false);
+    stairs.always_visible = true;
+    let mut hero = player();
+    hero.set_pos(1, 1);
+    let objects = vec![hero, stairs];
+
+    assert_eq!(map_to_ascii(&game, &objects), "####\n#@<#\n####\n");
+
+    let path = std::env::temp_dir().join("roguelike_test_map.txt");
+    let path = path.to_str().unwrap();
+    dump_map(&game, &objects, path).unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), map_to_ascii(&game, &objects));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn the_stairs_compass_points_only_at_stairs_already_found() {
+    let (_, mut game) = new_world(&Config::default(), Difficulty::default());
+    game.map = vec![vec![Tile::empty(); 10]; 10];
+    let mut hero = player();
+    hero.set_pos(5, 5);
+    let mut objects = vec![hero, Object::new(8, 2, '<', "Stairs", tcod::colors::WHITE, false)];
+
+    assert_eq!(stairs_direction(&objects, &game), None);
+
+    game.map[8][2].explored = true;
+    assert_eq!(stairs_direction(&objects, &game), Some("NE"));
+    objects[PLAYER].set_pos(5, 1);
+    assert_eq!(stairs_direction(&objects, &game), Some("E"));
+    objects[PLAYER].set_pos(8, 2);
+    assert_eq!(stairs_direction(&objects, &game), Some("here"));
+}
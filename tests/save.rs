@@ -0,0 +1,151 @@
+use std::env;
+
+use roguelike::*;
+
+fn slot(name: &str) -> String {
+    env::temp_dir()
+        .join(format!("roguelike-{}-{}", name, std::process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn deleted_save_cannot_be_loaded() {
+    let slot = slot("deleted");
+    let (objects, game) = new_world(&Config::default(), Difficulty::default());
+
+    save_game(&objects, &game, &slot).unwrap();
+    assert!(load_game(&slot).is_ok());
+
+    delete_save(&slot).unwrap();
+    assert!(load_game(&slot).is_err());
+}
+
+#[test]
+fn difficulty_survives_a_save() {
+    let slot = slot("difficulty");
+    let (objects, game) = new_world(&Config::default(), Difficulty::Hard);
+
+    save_game(&objects, &game, &slot).unwrap();
+    let (_, loaded) = load_game(&slot).unwrap();
+    assert_eq!(loaded.difficulty, Difficulty::Hard);
+
+    delete_save(&slot).unwrap();
+}
+
+#[test]
+fn theme_survives_a_save() {
+    let slot = slot("theme");
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    for _ in 0..3 {
+        descend(&mut objects, &mut game, &Config::default());
+    }
+    assert_eq!(game.theme, ThemeKind::Crypt);
+
+    save_game(&objects, &game, &slot).unwrap();
+    let (_, loaded) = load_game(&slot).unwrap();
+    assert_eq!(loaded.theme, ThemeKind::Crypt);
+
+    delete_save(&slot).unwrap();
+}
+
+#[test]
+fn deleting_a_missing_save_is_fine() {
+    assert!(delete_save(&slot("missing")).is_ok());
+}
+
+#[test]
+fn unversioned_save_is_migrated() {
+    let slot = slot("unversioned");
+    let (mut objects, game) = new_world(&Config::default(), Difficulty::default());
+    let mut orc = create_monster("orc", 1, 1, 1);
+    orc.ai = Some(Ai::Basic { last_known: Some((2, 2)), turns: 3 });
+    objects.push(orc);
+
+    // the old layout: a bare pair, with basic AI saved as a plain "Basic"
+    let mut pair = serde_json::to_value((&objects, &game)).unwrap();
+    let orc_id = objects.len() - 1;
+    pair[0][orc_id]["ai"] = "Basic".into();
+    std::fs::write(&slot, pair.to_string()).unwrap();
+
+    let (loaded, _) = load_game(&slot).unwrap();
+    assert_eq!(loaded.len(), objects.len());
+    assert_eq!(loaded[orc_id].ai, Some(Ai::Basic { last_known: None, turns: 0 }));
+
+    delete_save(&slot).unwrap();
+}
+
+#[test]
+fn save_from_a_newer_version_is_refused() {
+    let slot = slot("newer");
+    let (objects, game) = new_world(&Config::default(), Difficulty::default());
+
+    // written as plain json, which load_game still accepts
+    let save = serde_json::json!({ "version": SAVE_VERSION + 1, "objects": objects, "game": game });
+    std::fs::write(&slot, save.to_string()).unwrap();
+
+    assert!(load_game(&slot).is_err());
+    delete_save(&slot).unwrap();
+}
+
+#[test]
+fn damaged_save_is_reported_as_corrupted() {
+    let slot = slot("damaged");
+    let (objects, game) = new_world(&Config::default(), Difficulty::default());
+    save_game(&objects, &game, &slot).unwrap();
+
+    let mut bytes = std::fs::read(&slot).unwrap();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0xff;
+    std::fs::write(&slot, &bytes).unwrap();
+
+    let error = load_game(&slot).err().unwrap();
+    assert_eq!(error.to_string(), "save file corrupted");
+    delete_save(&slot).unwrap();
+}
+
+#[test]
+fn failed_save_leaves_the_previous_one_intact() {
+    let slot = slot("failed");
+    let (objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    save_game(&objects, &game, &slot).unwrap();
+
+    // a directory where the temporary file should go makes the write fail
+    let temp_path = format!("{}.tmp", slot);
+    std::fs::create_dir(&temp_path).unwrap();
+    game.turns = 42;
+    assert!(save_game(&objects, &game, &slot).is_err());
+    std::fs::remove_dir(&temp_path).unwrap();
+
+    let (_, loaded) = load_game(&slot).unwrap();
+    assert_eq!(loaded.turns, 0);
+    delete_save(&slot).unwrap();
+}
+
+#[test]
+fn a_recorded_run_comes_back_as_it_was_played() {
+    let path = slot("replay");
+    let mut replay = Replay::new(1234, Config::default());
+    replay.record(RecordedInput::Key { code: 0, printable: 'f', alt: false, ctrl: false, shift: false });
+    replay.record(RecordedInput::Idle(1));
+    replay.record(RecordedInput::Idle(1));
+    replay.record(RecordedInput::Mouse { cx: 3, cy: 4, lbutton_pressed: true, rbutton_pressed: false });
+    assert_eq!(replay.inputs.len(), 3);
+    assert_eq!(replay.inputs[1], RecordedInput::Idle(2));
+
+    save_replay(&replay, &path).unwrap();
+    let loaded = load_replay(&path).unwrap();
+    assert_eq!(loaded.seed, 1234);
+    assert_eq!(loaded.inputs, replay.inputs);
+    std::fs::remove_file(&path).unwrap();
+
+    // the same seed fills the first level the same way
+    let layouts: Vec<Vec<(String, (i32, i32))>> = (0..2)
+        .map(|_| {
+            seed_rng(loaded.seed);
+            let (objects, _) = new_world(&loaded.config, Difficulty::default());
+            objects.iter().map(|o| (o.name.clone(), o.pos())).collect()
+        })
+        .collect();
+    assert_eq!(layouts[0], layouts[1]);
+}
@@ -0,0 +1,50 @@
+use roguelike::*;
+
+#[test]
+fn the_shipped_spawns_file_matches_the_builtin_tables() {
+    let json = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/spawns.json")).unwrap();
+    let table: SpawnTable = serde_json::from_str(&json).unwrap();
+    assert_eq!(table, SpawnTable::default());
+    assert!(table.is_valid());
+}
+
+#[test]
+fn a_spawns_file_only_has_to_list_what_it_changes() {
+    let json = r#"{"monsters": [{"key": "orc", "name": "goblin", "glyph": "g", "color": {"r": 0, "g": 200, "b": 0},
+        "hp": 7, "defense": 0, "power": 2, "xp": 10, "weights": [{"level": 1, "value": 50}]}]}"#;
+    let table = serde_json::from_str::<SpawnTable>(json).unwrap().merged_over(SpawnTable::default());
+    assert!(table.monster("troll").is_some());
+    assert_eq!(table.items, SpawnTable::default().items);
+
+    set_spawn_table(table);
+    let goblin = create_monster("orc", 1, 1, 5);
+    assert_eq!(goblin.name, "goblin");
+    assert_eq!(goblin.char, 'g');
+    // nothing said it should grow with the level
+    assert_eq!(goblin.fighter.unwrap().base_max_hp, 7);
+    set_spawn_table(SpawnTable::default());
+}
+
+#[test]
+fn an_item_without_a_callback_or_lore_keeps_the_builtin_ones() {
+    let json = r#"{"items": [{"item": "Ration", "name": "hardtack", "glyph": "%", "color": {"r": 200, "g": 150, "b": 50},
+        "weights": [{"level": 1, "value": 20}]}]}"#;
+    let table = serde_json::from_str::<SpawnTable>(json).unwrap().merged_over(SpawnTable::default());
+    let ration = table.item(Item::Ration).unwrap();
+    assert_eq!(ration.name, "hardtack");
+    assert_eq!(ration.on_use, Some(UseCallback::Eat));
+    assert!(ration.lore.starts_with("Dried meat"));
+}
+
+#[test]
+fn a_table_the_theme_scales_down_to_nothing_is_invalid() {
+    let mut table = SpawnTable::default();
+    // the caves halve the troll's chances, and half of one rounds down to nothing
+    table.monsters.retain(|template| template.key == "troll");
+    table.monsters[0].weights = vec![Transition { level: 1, value: 1 }];
+    assert!(!table.is_valid());
+
+    set_spawn_table(table);
+    assert_eq!(random_monster(1), "troll");
+    set_spawn_table(SpawnTable::default());
+}
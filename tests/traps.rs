@@ -0,0 +1,97 @@
+use roguelike::*;
+
+// open floor with one hidden trap
+fn trapped_floor(game: &mut Game, (x, y): (i32, i32)) {
+    game.map = vec![vec![Tile::empty(); 20]; 20];
+    game.map[x as usize][y as usize].trap = Some(Trap { kind: TrapKind::Spikes, found: false });
+}
+
+#[test]
+fn stepping_on_a_hidden_trap_springs_and_reveals_it() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects[PLAYER].set_pos(5, 5);
+    trapped_floor(&mut game, (6, 5));
+    let hp = objects[PLAYER].fighter.unwrap().hp;
+
+    player_move_or_attack(1, 0, false, &mut objects, &mut game);
+    assert_eq!(objects[PLAYER].pos(), (6, 5));
+    assert!(objects[PLAYER].fighter.unwrap().hp < hp);
+    assert!(game.map[6][5].trap.unwrap().found);
+}
+
+#[test]
+fn swapping_places_with_an_ally_onto_a_trap_springs_it() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects.truncate(PLAYER + 1);
+    objects[PLAYER].set_pos(5, 5);
+    trapped_floor(&mut game, (6, 5));
+    let mut ally = create_monster("orc", 6, 5, 1);
+    ally.ai = Some(Ai::Allied { lifetime: ALLY_LIFETIME });
+    objects.push(ally);
+    let hp = objects[PLAYER].fighter.unwrap().hp;
+
+    player_move_or_attack(1, 0, false, &mut objects, &mut game);
+    assert_eq!(objects[PLAYER].pos(), (6, 5));
+    assert_eq!(objects[1].pos(), (5, 5));
+    assert!(objects[PLAYER].fighter.unwrap().hp < hp);
+    assert!(game.map[6][5].trap.unwrap().found);
+}
+
+#[test]
+fn searching_finds_nearby_traps_and_disarming_clears_or_springs_them() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects[PLAYER].set_pos(5, 5);
+    trapped_floor(&mut game, (6, 6));
+
+    assert!(!disarm_trap(&mut objects, &mut game));
+    while search_for_traps(&objects, &mut game) == 0 {}
+    assert!(game.map[6][6].trap.unwrap().found);
+
+    while game.map[6][6].trap.is_some() {
+        objects[PLAYER].fighter.as_mut().unwrap().hp = 100;
+        assert!(disarm_trap(&mut objects, &mut game));
+    }
+}
+
+#[test]
+fn falling_through_a_chasm_hurts_and_lands_on_the_level_below() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    let (x, y) = objects[PLAYER].pos();
+    game.map[x as usize][y as usize] = Tile::chasm();
+    assert!(objects[PLAYER].over_chasm(&game.map));
+    let hp = objects[PLAYER].fighter.unwrap().hp;
+
+    fall_into_chasm(&mut objects, &mut game, &Config::default());
+    assert_eq!(game.dungeon_level, 2);
+    assert!(!objects[PLAYER].over_chasm(&game.map));
+    assert_eq!(objects[PLAYER].fighter.unwrap().hp, hp - objects[PLAYER].resisted_damage(FALL_DAMAGE, DamageType::Physical));
+    assert_eq!(game.last_attacker, Some("a fall".to_string()));
+}
+
+#[test]
+fn levitation_floats_over_chasms_and_monsters_fall_in() {
+    let (mut objects, mut game) = new_world(&Config::default(), Difficulty::default());
+    objects.truncate(1);
+    objects[PLAYER].set_pos(5, 5);
+    game.map = vec![vec![Tile::empty(); 20]; 20];
+    game.map[5][5] = Tile::chasm();
+    game.map[10][10] = Tile::chasm();
+
+    game.inventory.push(create_item(Item::LevitationPotion, 0, 0));
+    apply_item(Item::LevitationPotion, 0, &mut objects, &mut game);
+    assert!(!objects[PLAYER].over_chasm(&game.map));
+
+    let mut orc = create_monster("orc", 10, 10, 1);
+    orc.name = "clumsy orc".into();
+    orc.ai = Some(Ai::Sleeping);
+    objects.push(orc);
+    end_player_turn(&mut objects, &mut game, &tcod::map::Map::new(20, 20));
+    assert_eq!(objects.len(), 1);
+    assert_eq!(game.fallen.len(), 1);
+
+    // it turns up again on the level below
+    descend(&mut objects, &mut game, &Config::default());
+    assert!(game.fallen.is_empty());
+    let orc = objects.iter().find(|o| o.name == "clumsy orc").unwrap();
+    assert!(!game.map[orc.x as usize][orc.y as usize].chasm);
+}